@@ -1,15 +1,86 @@
-use std::{collections::HashSet, fs, iter};
+use std::{collections::HashSet, fmt, fs, process};
 
+use unicode_segmentation::UnicodeSegmentation;
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum GridError {
+    Empty,
+    RaggedRow {
+        row: usize,
+        expected: usize,
+        found: usize,
+    },
+}
+
+impl fmt::Display for GridError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GridError::Empty => write!(f, "grid has no rows"),
+            GridError::RaggedRow {
+                row,
+                expected,
+                found,
+            } => write!(
+                f,
+                "row {} has {} cells but expected {} (pass --pad to pad short rows)",
+                row + 1,
+                found,
+                expected
+            ),
+        }
+    }
+}
+
+// Each cell is a single extended grapheme cluster, so multi-codepoint symbols
+// (e.g. emoji with modifiers) occupy exactly one column.
 pub struct Grid<'a> {
-    rows: &'a Vec<Vec<char>>,
+    rows: Vec<Vec<&'a str>>,
     width: usize,
     height: usize,
 }
 
 impl<'a> Grid<'a> {
-    pub fn new(rows: &'a Vec<Vec<char>>) -> Grid {
+    const PADDING: &'static str = ".";
+
+    pub fn parse(input: &'a str) -> Result<Grid<'a>, GridError> {
+        let rows = Grid::split_rows(input);
+        let width = rows.first().ok_or(GridError::Empty)?.len();
+        if let Some((row, cells)) = rows
+            .iter()
+            .enumerate()
+            .find(|(_, cells)| cells.len() != width)
+        {
+            return Err(GridError::RaggedRow {
+                row,
+                expected: width,
+                found: cells.len(),
+            });
+        }
+        Ok(Grid::new(rows, width))
+    }
+
+    pub fn parse_padded(input: &'a str) -> Result<Grid<'a>, GridError> {
+        let mut rows = Grid::split_rows(input);
+        let width = rows
+            .iter()
+            .map(|cells| cells.len())
+            .max()
+            .ok_or(GridError::Empty)?;
+        for cells in rows.iter_mut() {
+            cells.resize(width, Grid::PADDING);
+        }
+        Ok(Grid::new(rows, width))
+    }
+
+    fn split_rows(input: &'a str) -> Vec<Vec<&'a str>> {
+        input
+            .lines()
+            .map(|line| line.graphemes(true).collect())
+            .collect()
+    }
+
+    fn new(rows: Vec<Vec<&'a str>>, width: usize) -> Grid<'a> {
         let height = rows.len();
-        let width = rows[0].len();
         Grid {
             rows,
             width,
@@ -17,7 +88,7 @@ impl<'a> Grid<'a> {
         }
     }
 
-    pub fn adjacent_values(&self, x: usize, y: usize) -> Vec<char> {
+    pub fn adjacent_values(&self, x: usize, y: usize) -> Vec<&'a str> {
         self.adjacent_points(x, y)
             .iter()
             .map(|(x_adj, y_adj)| self.rows[*y_adj][*x_adj])
@@ -46,100 +117,173 @@ impl<'a> Grid<'a> {
 fn main() {
     let args: Vec<String> = std::env::args().collect();
     let file_name = args.get(1).unwrap();
+    let pad = args.iter().any(|arg| arg == "--pad");
+
+    let file = fs::read_to_string(file_name).unwrap();
+    let grid = if pad {
+        Grid::parse_padded(&file)
+    } else {
+        Grid::parse(&file)
+    };
+    let grid = grid.unwrap_or_else(|err| {
+        eprintln!("Invalid grid in {file_name}: {err}");
+        process::exit(1);
+    });
+
+    let (part_numbers, part_number_grid) = find_part_numbers(&grid);
 
-    let grid: Vec<Vec<char>> = fs::read_to_string(file_name)
-        .unwrap()
-        .lines()
-        .map(|line| line.chars().collect::<Vec<char>>())
-        .collect();
+    let sum: u32 = part_numbers.iter().sum();
+    println!("Sum: {}", sum);
 
-    let grid = Grid::new(&grid);
+    let mut sum_of_gear_ratios: u32 = 0;
+    for (y, row) in grid.rows.iter().enumerate() {
+        for (x, cell) in row.iter().enumerate() {
+            if *cell != "*" {
+                continue;
+            }
+            let adjacent_points = grid.adjacent_points(x, y);
+            let adjacent_part_numbers: HashSet<u32> = adjacent_points
+                .iter()
+                .filter_map(|(dx, dy)| part_number_grid[*dy][*dx])
+                .collect();
+            if adjacent_part_numbers.len() == 2 {
+                sum_of_gear_ratios += adjacent_part_numbers.iter().product::<u32>()
+            }
+        }
+    }
+
+    println!("Gears: {}", sum_of_gear_ratios);
+}
 
+// Numbers touching a symbol, in reading order, and a grid marking each cell
+// of those numbers with the number it belongs to.
+fn find_part_numbers(grid: &Grid) -> (Vec<u32>, Vec<Vec<Option<u32>>>) {
     let mut part_numbers: Vec<u32> = vec![];
     let mut part_number_grid = create_part_number_grid(grid.width, grid.height);
     for (y, row) in grid.rows.iter().enumerate() {
-        let mut number: Vec<char> = vec![];
+        let mut number: Vec<&str> = vec![];
         let mut is_part_number = false;
-        for (x, char) in row.iter().enumerate() {
-            match char.to_digit(10) {
-                Some(_) => number.push(*char),
-                None => {
-                    if is_part_number {
-                        record_part_number(&mut part_numbers, &mut part_number_grid, x, y, &number);
-                    }
-                    number = vec![];
-                    is_part_number = false;
-                    continue;
+        for (x, cell) in row.iter().enumerate() {
+            if is_digit(cell) {
+                number.push(cell);
+            } else {
+                if is_part_number {
+                    record_part_number(&mut part_numbers, &mut part_number_grid, x, y, &number);
                 }
+                number = vec![];
+                is_part_number = false;
+                continue;
             }
             let adjacent = grid.adjacent_values(x, y);
-            if adjacent.iter().any(|c| is_symbol(c)) {
+            if adjacent.iter().any(|cell| is_symbol(cell)) {
                 is_part_number = true;
             }
         }
-        if number.len() > 0 && is_part_number {
+        if !number.is_empty() && is_part_number {
             record_part_number(
                 &mut part_numbers,
                 &mut part_number_grid,
-                grid.width - 1,
+                grid.width,
                 y,
                 &number,
             );
         }
     }
-
-    let sum: u32 = part_numbers.iter().sum();
-    println!("Sum: {}", sum);
-
-    let mut sum_of_gear_ratios: u32 = 0;
-    for (y, row) in grid.rows.iter().enumerate() {
-        for (x, character) in row.iter().enumerate() {
-            if *character != '*' {
-                continue;
-            }
-            let adjacent_points = grid.adjacent_points(x, y);
-            let adjacent_part_numbers: HashSet<u32> = adjacent_points
-                .iter()
-                .map(|(dx, dy)| part_number_grid[*dy][*dx])
-                .filter(|val| val.is_some())
-                .map(|val| val.unwrap())
-                .collect();
-            if adjacent_part_numbers.len() == 2 {
-                sum_of_gear_ratios += adjacent_part_numbers.iter().fold(1, |acc, v| acc * v)
-            }
-        }
-    }
-
-    println!("Gears: {}", sum_of_gear_ratios);
+    (part_numbers, part_number_grid)
 }
 
 fn record_part_number(
     part_numbers: &mut Vec<u32>,
-    grid: &mut Vec<Vec<Option<u32>>>,
+    grid: &mut [Vec<Option<u32>>],
     x: usize,
     y: usize,
-    number: &Vec<char>,
+    number: &[&str],
 ) {
-    let part_number = chars_to_number(number);
+    let part_number = cells_to_number(number);
     part_numbers.push(part_number);
     for dx in 0..number.len() {
         grid[y][x - 1 - dx] = Some(part_number);
     }
 }
 
-fn is_symbol(character: &char) -> bool {
-    if character.to_digit(10).is_some() {
-        return false;
-    }
-    return *character != '.';
+fn is_digit(cell: &str) -> bool {
+    let mut chars = cell.chars();
+    matches!((chars.next(), chars.next()), (Some(c), None) if c.is_ascii_digit())
+}
+
+fn is_symbol(cell: &str) -> bool {
+    !is_digit(cell) && cell != "."
 }
 
-fn chars_to_number(chars: &Vec<char>) -> u32 {
-    chars.iter().collect::<String>().parse().unwrap()
+fn cells_to_number(cells: &[&str]) -> u32 {
+    cells.concat().parse().unwrap()
 }
 
 fn create_part_number_grid(width: usize, height: usize) -> Vec<Vec<Option<u32>>> {
-    iter::repeat(iter::repeat(None).take(width).collect())
-        .take(height)
-        .collect()
+    vec![vec![None; width]; height]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_treat_grapheme_clusters_as_single_cells() {
+        let input = "1👍🏽.\n.2e\u{301}";
+
+        let grid = Grid::parse(input).unwrap();
+
+        assert_eq!(3, grid.width);
+        assert_eq!(vec!["1", "👍🏽", "."], grid.rows[0]);
+        assert_eq!(vec![".", "2", "e\u{301}"], grid.rows[1]);
+    }
+
+    #[test]
+    fn should_reject_ragged_rows() {
+        let input = "467..\n...*\n..35.";
+
+        let result = Grid::parse(input);
+
+        let expected = GridError::RaggedRow {
+            row: 1,
+            expected: 5,
+            found: 4,
+        };
+        assert_eq!(Some(expected), result.err());
+    }
+
+    #[test]
+    fn should_pad_ragged_rows() {
+        let input = "467\n.*\n..35";
+
+        let grid = Grid::parse_padded(input).unwrap();
+
+        assert_eq!(4, grid.width);
+        assert_eq!(vec![".", "*", ".", "."], grid.rows[1]);
+        assert_eq!(vec!["7", ".", ".", "."], grid.adjacent_values(3, 0));
+    }
+
+    #[test]
+    fn should_reject_empty_grid() {
+        assert_eq!(Some(GridError::Empty), Grid::parse("").err());
+    }
+
+    #[test]
+    fn should_count_part_number_in_last_column() {
+        let grid = Grid::parse("...*\n..12\n58..").unwrap();
+
+        let (part_numbers, part_number_grid) = find_part_numbers(&grid);
+
+        assert_eq!(vec![12], part_numbers);
+        assert_eq!(vec![None, None, Some(12), Some(12)], part_number_grid[1]);
+    }
+
+    #[test]
+    fn should_treat_unicode_symbols_as_symbols() {
+        assert!(is_symbol("👍🏽"));
+        assert!(is_symbol("*"));
+        assert!(!is_symbol("."));
+        assert!(!is_symbol("7"));
+        assert!(!is_digit("7\u{20E3}"));
+    }
 }
@@ -1,80 +1,648 @@
-use std::{collections::HashSet, fs};
+use std::{collections::HashSet, fmt, fs, ops::Range, process};
 
-#[derive(Clone)]
+#[derive(Debug, PartialEq, Eq)]
+pub enum CardError {
+    NonContiguousId { expected: usize, found: usize },
+}
+
+impl fmt::Display for CardError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CardError::NonContiguousId { expected, found } => {
+                write!(f, "expected Card {expected} but found Card {found}")
+            }
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
 pub struct Scratchcard {
-    number: usize,
-    num_matches: usize,
+    id: usize,
+    winning_numbers: Vec<u32>,
+    held_numbers: Vec<u32>,
 }
 
 impl Scratchcard {
-    pub fn parse(number: usize, row: &str) -> Scratchcard {
-        let parts: Vec<&str> = row.split("|").collect();
-        let winning_numbers = Scratchcard::parse_numbers(parts[0]);
-        let my_numbers = Scratchcard::parse_numbers(parts[1]);
-        let num_matches = Scratchcard::num_matches(winning_numbers, my_numbers);
+    pub fn parse(row: &str) -> Scratchcard {
+        let (label, numbers) = row.split_once(':').unwrap();
+        let id = label.trim_start_matches("Card").trim().parse().unwrap();
+        let (winning_numbers, held_numbers) = numbers.split_once('|').unwrap();
         Scratchcard {
-            number,
-            num_matches,
+            id,
+            winning_numbers: Scratchcard::parse_numbers(winning_numbers),
+            held_numbers: Scratchcard::parse_numbers(held_numbers),
         }
     }
 
-    fn parse_numbers(numbers: &str) -> HashSet<u32> {
+    fn parse_numbers(numbers: &str) -> Vec<u32> {
         numbers
-            .trim()
-            .split(' ')
-            .filter(|number| !number.is_empty())
+            .split_ascii_whitespace()
             .map(|number| number.parse().unwrap())
             .collect()
     }
 
-    fn num_matches(winning_numbers: HashSet<u32>, my_numbers: HashSet<u32>) -> usize {
-        winning_numbers.intersection(&my_numbers).count()
+    pub fn id(&self) -> usize {
+        self.id
+    }
+
+    pub fn winning_numbers(&self) -> &[u32] {
+        &self.winning_numbers
+    }
+
+    pub fn held_numbers(&self) -> &[u32] {
+        &self.held_numbers
+    }
+
+    pub fn matches(&self) -> usize {
+        let winning_numbers: HashSet<&u32> = self.winning_numbers.iter().collect();
+        let held_numbers: HashSet<&u32> = self.held_numbers.iter().collect();
+        winning_numbers.intersection(&held_numbers).count()
+    }
+
+    pub fn points(&self) -> u64 {
+        ScoringRule::Doubling.score(self.matches())
+    }
+
+    // Ids of the cards this card wins a copy of. The range may run past the end
+    // of the table; callers clamp it to the cards they hold.
+    pub fn copies_won(&self) -> Range<usize> {
+        let from = self.id + 1;
+        from..from.saturating_add(self.matches())
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum ScoringRule {
+    // 1, 2, 4, 8, ... points: the puzzle's rule
+    Doubling,
+    // One point per match
+    Linear,
+    // 1, 2, 3, 5, 8, ... points
+    Fibonacci,
+    // Points indexed by match count; counts past the end score the last entry
+    Table(Vec<u64>),
+}
+
+impl ScoringRule {
+    pub fn parse(arg: &str) -> Result<ScoringRule, String> {
+        match arg.split_once(':') {
+            None => match arg {
+                "doubling" => Ok(ScoringRule::Doubling),
+                "linear" => Ok(ScoringRule::Linear),
+                "fibonacci" => Ok(ScoringRule::Fibonacci),
+                _ => Err(format!("unknown scoring rule: {arg}")),
+            },
+            Some(("table", values)) => values
+                .split(',')
+                .map(|value| value.trim().parse())
+                .collect::<Result<Vec<u64>, _>>()
+                .map(ScoringRule::Table)
+                .map_err(|_| format!("invalid scoring table: {values}")),
+            Some(_) => Err(format!("unknown scoring rule: {arg}")),
+        }
+    }
+
+    pub fn score(&self, matches: usize) -> u64 {
+        match self {
+            ScoringRule::Doubling => match matches {
+                0 => 0,
+                _ => 2u64.saturating_pow((matches - 1).min(u32::MAX as usize) as u32),
+            },
+            ScoringRule::Linear => matches as u64,
+            ScoringRule::Fibonacci => match matches {
+                0 => 0,
+                _ => {
+                    let (mut previous, mut current) = (1u64, 2u64);
+                    for _ in 1..matches {
+                        (previous, current) = (current, previous.saturating_add(current));
+                    }
+                    previous
+                }
+            },
+            ScoringRule::Table(values) => {
+                values.get(matches).or(values.last()).copied().unwrap_or(0)
+            }
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum CopyRule {
+    // Copies of the next N cards, stopping at the end of the table: the puzzle's rule
+    NextN,
+    // Copies past the end of the table wrap around to the first card. Those cards
+    // have already been scratched, so wrapped copies are held but win nothing.
+    Wraparound,
+    // As NextN, but a card wins at most this many copies
+    Capped(usize),
+}
+
+impl CopyRule {
+    pub fn parse(arg: &str) -> Result<CopyRule, String> {
+        match arg.split_once(':') {
+            None => match arg {
+                "next" => Ok(CopyRule::NextN),
+                "wrap" => Ok(CopyRule::Wraparound),
+                _ => Err(format!("unknown copy rule: {arg}")),
+            },
+            Some(("cap", max)) => max
+                .trim()
+                .parse()
+                .map(CopyRule::Capped)
+                .map_err(|_| format!("invalid copy cap: {max}")),
+            Some(_) => Err(format!("unknown copy rule: {arg}")),
+        }
+    }
+
+    pub fn copies(&self, matches: usize) -> usize {
+        match self {
+            CopyRule::NextN | CopyRule::Wraparound => matches,
+            CopyRule::Capped(max) => matches.min(*max),
+        }
+    }
+
+    // Where the copies won by the card at `index` land in a table of `len` cards
+    fn span(&self, matches: usize, index: usize, len: usize) -> CopySpan {
+        let copies = self.copies(matches);
+        let forward = copies.min(len - index - 1);
+        if *self != CopyRule::Wraparound {
+            return CopySpan {
+                forward,
+                full_laps: 0,
+                wrapped: 0,
+            };
+        }
+        let wrapped = copies - forward;
+        CopySpan {
+            forward,
+            full_laps: wrapped / len,
+            wrapped: wrapped % len,
+        }
     }
 }
 
+// The cards after this one that are copied, followed by any copies that wrap
+// around: full laps of the whole table and then the first `wrapped` cards.
+struct CopySpan {
+    forward: usize,
+    full_laps: usize,
+    wrapped: usize,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct CardTrace {
+    id: usize,
+    held: u64,
+    copies_generated: u64,
+    // Inclusive (first, last) runs of card ids
+    copied: Vec<(usize, usize)>,
+}
+
 fn main() {
     let args: Vec<String> = std::env::args().collect();
     let file_name = args.get(1).unwrap();
 
-    let all_cards: Vec<Scratchcard> = fs::read_to_string(file_name)
-        .unwrap()
-        .lines()
-        .map(|line| line.split(":").last().unwrap())
-        .enumerate()
-        .map(|(row_num, values)| Scratchcard::parse(row_num + 1, values))
-        .collect();
+    let all_cards = parse_cards(&fs::read_to_string(file_name).unwrap()).unwrap_or_else(|err| {
+        eprintln!("Invalid scratchcards in {file_name}: {err}");
+        process::exit(1);
+    });
 
-    let total_score: u32 = all_cards
+    let scoring_rule = parse_flag(
+        &args,
+        "--scoring=",
+        ScoringRule::parse,
+        ScoringRule::Doubling,
+    );
+    let copy_rule = parse_flag(&args, "--copies=", CopyRule::parse, CopyRule::NextN);
+
+    let total_score: u64 = all_cards
         .iter()
-        .map(|card| card.num_matches)
-        .filter(|num_matches| *num_matches != 0)
-        .map(|num_matches| 2u32.pow((num_matches - 1) as u32))
-        .sum();
+        .map(|card| scoring_rule.score(card.matches()))
+        .fold(0u64, |total, points| total.saturating_add(points));
     println!("Part 1: {}", total_score);
 
-    let final_cards = score_cards(&all_cards, all_cards.clone(), all_cards.len());
+    let final_cards =
+        count_cards(&all_cards, &copy_rule).expect("Part 2 card count overflowed u64");
     println!("Part 2: {}", final_cards);
+
+    if args.iter().any(|arg| arg == "--trace") {
+        let traces = trace_cards(&all_cards, &copy_rule).expect("Part 2 trace overflowed u64");
+        println!();
+        print!("{}", format_traces(&traces));
+        println!();
+        print!("{}", format_histogram(&traces, 50));
+    }
 }
 
-fn score_cards(all_cards: &Vec<Scratchcard>, my_cards: Vec<Scratchcard>, count: usize) -> usize {
-    let mut copies: Vec<Scratchcard> = vec![];
-    for scratchcard in my_cards.iter() {
-        let num_copies = scratchcard.num_matches;
-        if num_copies == 0 {
-            continue;
+fn parse_flag<T>(
+    args: &[String],
+    prefix: &str,
+    parse: fn(&str) -> Result<T, String>,
+    default: T,
+) -> T {
+    match args.iter().find_map(|arg| arg.strip_prefix(prefix)) {
+        Some(value) => parse(value).unwrap_or_else(|err| {
+            eprintln!("{err}");
+            process::exit(1);
+        }),
+        None => default,
+    }
+}
+
+fn parse_cards(input: &str) -> Result<Vec<Scratchcard>, CardError> {
+    let cards: Vec<Scratchcard> = input.lines().map(Scratchcard::parse).collect();
+    for pair in cards.windows(2) {
+        let expected = pair[0].id + 1;
+        if pair[1].id != expected {
+            return Err(CardError::NonContiguousId {
+                expected,
+                found: pair[1].id,
+            });
         }
+    }
+    Ok(cards)
+}
 
-        let from = scratchcard.number;
-        let until = scratchcard.number + num_copies;
-        let mut to_add: Vec<Scratchcard> =
-            all_cards[from..until].iter().map(|c| c.clone()).collect();
-        copies.append(&mut to_add)
+fn count_cards(cards: &[Scratchcard], rule: &CopyRule) -> Option<u64> {
+    count_instances(cards, rule)?
+        .iter()
+        .try_fold(0u64, |total, instances| total.checked_add(*instances))
+}
+
+fn count_instances(cards: &[Scratchcard], rule: &CopyRule) -> Option<Vec<u64>> {
+    let (scratched, wrapped) = scratch_cards(cards, rule)?;
+    scratched
+        .iter()
+        .zip(wrapped)
+        .map(|(scratched, wrapped)| scratched.checked_add(wrapped))
+        .collect()
+}
+
+// Each card's instance count is final once every earlier card has been seen, so
+// a single pass suffices. Copies won by a card are added to a running bonus and
+// queued to expire after the last card they apply to, which keeps the pass
+// linear no matter how many matches a card has. Wrapped copies don't cascade,
+// so they are tallied separately from the instances that get scratched. Ids
+// must be contiguous, as checked by parse_cards.
+fn scratch_cards(cards: &[Scratchcard], rule: &CopyRule) -> Option<(Vec<u64>, Vec<u64>)> {
+    let mut scratched: Vec<u64> = Vec::with_capacity(cards.len());
+    let mut expiring: Vec<u64> = vec![0; cards.len() + 1];
+    let mut bonus: u64 = 0;
+    let mut wrapped_starting: u64 = 0;
+    let mut wrapped_expiring: Vec<u64> = vec![0; cards.len() + 1];
+    for (index, card) in cards.iter().enumerate() {
+        bonus -= expiring[index];
+        let held = bonus.checked_add(1)?;
+        scratched.push(held);
+
+        let span = rule.span(card.matches(), index, cards.len());
+        if span.forward > 0 {
+            bonus = bonus.checked_add(held)?;
+            let until = index + 1 + span.forward;
+            expiring[until] = expiring[until].checked_add(held)?;
+        }
+        let full_laps = held.checked_mul(span.full_laps as u64)?;
+        wrapped_starting = wrapped_starting.checked_add(full_laps)?;
+        if span.wrapped > 0 {
+            wrapped_starting = wrapped_starting.checked_add(held)?;
+            wrapped_expiring[span.wrapped] = wrapped_expiring[span.wrapped].checked_add(held)?;
+        }
     }
 
-    let new_count = count + copies.len();
-    if copies.len() == 0 {
-        return new_count;
+    let mut running = wrapped_starting;
+    let wrapped = wrapped_expiring[..cards.len()]
+        .iter()
+        .map(|expiring| {
+            running -= expiring;
+            running
+        })
+        .collect();
+    Some((scratched, wrapped))
+}
+
+fn trace_cards(cards: &[Scratchcard], rule: &CopyRule) -> Option<Vec<CardTrace>> {
+    let (scratched, wrapped) = scratch_cards(cards, rule)?;
+    let first_id = cards.first().map(|card| card.id).unwrap_or_default();
+    let last_id = first_id + cards.len().saturating_sub(1);
+    cards
+        .iter()
+        .zip(scratched.into_iter().zip(wrapped))
+        .enumerate()
+        .map(|(index, (card, (scratched, wrapped)))| {
+            let span = rule.span(card.matches(), index, cards.len());
+            let mut copied = vec![];
+            if span.forward > 0 {
+                copied.push((card.id + 1, card.id + span.forward));
+            }
+            copied.extend(std::iter::repeat_n((first_id, last_id), span.full_laps));
+            if span.wrapped > 0 {
+                copied.push((first_id, first_id + span.wrapped - 1));
+            }
+            let copies = copied
+                .iter()
+                .map(|(first, last)| (last - first + 1) as u64)
+                .sum::<u64>();
+            Some(CardTrace {
+                id: card.id,
+                held: scratched.checked_add(wrapped)?,
+                copies_generated: scratched.checked_mul(copies)?,
+                copied,
+            })
+        })
+        .collect()
+}
+
+fn format_traces(traces: &[CardTrace]) -> String {
+    let mut output = format!("{:>6} {:>12} {:>12}  Copied\n", "Card", "Held", "Generated");
+    for trace in traces {
+        let copied: Vec<String> = trace
+            .copied
+            .iter()
+            .map(|(first, last)| {
+                if first == last {
+                    first.to_string()
+                } else {
+                    format!("{first}-{last}")
+                }
+            })
+            .collect();
+        let copied = if copied.is_empty() {
+            "-".to_string()
+        } else {
+            copied.join(", ")
+        };
+        output += &format!(
+            "{:>6} {:>12} {:>12}  {}\n",
+            trace.id, trace.held, trace.copies_generated, copied
+        );
     }
+    output
+}
 
-    score_cards(all_cards, copies, new_count)
+// One bar per card, scaled so the most-held card fills `width` columns. Any
+// card held at all gets at least one column.
+fn format_histogram(traces: &[CardTrace], width: usize) -> String {
+    let max_held = traces.iter().map(|trace| trace.held).max().unwrap_or(0);
+    let mut output = String::from("Instances held\n");
+    for trace in traces {
+        let columns = (trace.held as u128 * width as u128).div_ceil(max_held.max(1) as u128);
+        output += &format!(
+            "{:>6} | {} {}\n",
+            trace.id,
+            "#".repeat(columns as usize),
+            trace.held
+        );
+    }
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // The original recursive implementation, kept as a reference for count_cards.
+    // Cards are tracked by index into all_cards rather than cloned.
+    fn score_cards(all_cards: &[Scratchcard], my_cards: Vec<usize>, count: usize) -> usize {
+        let mut copies: Vec<usize> = vec![];
+        for index in my_cards.iter() {
+            let num_copies = all_cards[*index].matches();
+            if num_copies == 0 {
+                continue;
+            }
+
+            let from = index + 1;
+            let until = from + num_copies;
+            copies.extend(from..until)
+        }
+
+        let new_count = count + copies.len();
+        if copies.is_empty() {
+            return new_count;
+        }
+
+        score_cards(all_cards, copies, new_count)
+    }
+
+    // Builds a table of cards with ids 1..=n where each card has the given
+    // number of matches.
+    fn cards(matches: &[usize]) -> Vec<Scratchcard> {
+        matches
+            .iter()
+            .enumerate()
+            .map(|(index, matches)| Scratchcard {
+                id: index + 1,
+                winning_numbers: (0..*matches as u32).collect(),
+                held_numbers: (0..*matches as u32).collect(),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn should_parse_card() {
+        let card = Scratchcard::parse("Card  12: 41 48 83 | 83 86  6 48");
+
+        let expected = Scratchcard {
+            id: 12,
+            winning_numbers: vec![41, 48, 83],
+            held_numbers: vec![83, 86, 6, 48],
+        };
+        assert_eq!(expected, card);
+        assert_eq!(2, card.matches());
+        assert_eq!(2, card.points());
+        assert_eq!(13..15, card.copies_won());
+    }
+
+    #[test]
+    fn should_score_example_cards() {
+        let cards = parse_cards(include_str!("../resources/test.txt")).unwrap();
+
+        let points: Vec<u64> = cards.iter().map(|card| card.points()).collect();
+
+        assert_eq!(vec![8, 2, 2, 1, 0, 0], points);
+    }
+
+    #[test]
+    fn should_reject_non_contiguous_ids() {
+        let input = "Card 1: 1 | 1\nCard 2: 2 | 3\nCard 4: 5 | 6";
+
+        let result = parse_cards(input);
+
+        let expected = CardError::NonContiguousId {
+            expected: 3,
+            found: 4,
+        };
+        assert_eq!(Some(expected), result.err());
+    }
+
+    #[test]
+    fn should_match_recursive_implementation_on_example() {
+        let cards = parse_cards(include_str!("../resources/test.txt")).unwrap();
+
+        let expected = score_cards(&cards, (0..cards.len()).collect(), cards.len());
+
+        assert_eq!(30, expected);
+        assert_eq!(Some(expected as u64), count_cards(&cards, &CopyRule::NextN));
+    }
+
+    #[test]
+    fn should_clamp_copies_to_end_of_table() {
+        let cards = cards(&[5, 0, 0]);
+
+        assert_eq!(Some(5), count_cards(&cards, &CopyRule::NextN));
+    }
+
+    #[test]
+    fn should_count_many_cards_in_linear_time() {
+        let cards = cards(&[1; 200_000]);
+
+        // Card n is held n times: once originally and once for each copy of card n - 1.
+        let expected: u64 = (1..=200_000u64).sum();
+        assert_eq!(Some(expected), count_cards(&cards, &CopyRule::NextN));
+    }
+
+    #[test]
+    fn should_handle_large_match_counts() {
+        let mut cards = cards(&[0; 300_000]);
+        cards[0].winning_numbers = (0..1_000_000).collect();
+        cards[0].held_numbers = (0..1_000_000).collect();
+
+        assert_eq!(Some(599_999), count_cards(&cards, &CopyRule::NextN));
+    }
+
+    #[test]
+    fn should_report_overflow() {
+        let cards = cards(&[100; 100]);
+
+        assert_eq!(None, count_cards(&cards, &CopyRule::NextN));
+    }
+
+    #[test]
+    fn should_score_with_each_rule() {
+        let matches = [0, 1, 2, 3, 4, 5];
+        let score = |rule: ScoringRule| -> Vec<u64> {
+            matches.iter().map(|matches| rule.score(*matches)).collect()
+        };
+
+        assert_eq!(vec![0, 1, 2, 4, 8, 16], score(ScoringRule::Doubling));
+        assert_eq!(vec![0, 1, 2, 3, 4, 5], score(ScoringRule::Linear));
+        assert_eq!(vec![0, 1, 2, 3, 5, 8], score(ScoringRule::Fibonacci));
+        assert_eq!(
+            vec![0, 10, 30, 30, 30, 30],
+            score(ScoringRule::Table(vec![0, 10, 30]))
+        );
+    }
+
+    #[test]
+    fn should_parse_rules() {
+        assert_eq!(Ok(ScoringRule::Linear), ScoringRule::parse("linear"));
+        assert_eq!(
+            Ok(ScoringRule::Table(vec![0, 1, 3])),
+            ScoringRule::parse("table:0,1,3")
+        );
+        assert!(ScoringRule::parse("table:0,x").is_err());
+        assert!(ScoringRule::parse("squares").is_err());
+        assert_eq!(Ok(CopyRule::Wraparound), CopyRule::parse("wrap"));
+        assert_eq!(Ok(CopyRule::Capped(2)), CopyRule::parse("cap:2"));
+        assert!(CopyRule::parse("cap:-1").is_err());
+    }
+
+    #[test]
+    fn should_cap_copies() {
+        let cards = parse_cards(include_str!("../resources/test.txt")).unwrap();
+
+        let instances = count_instances(&cards, &CopyRule::Capped(1));
+
+        // Each winning card only copies the card after it.
+        assert_eq!(Some(vec![1, 2, 3, 4, 5, 1]), instances);
+    }
+
+    #[test]
+    fn should_wrap_copies_without_cascading() {
+        let cards = cards(&[0, 1, 3]);
+
+        let instances = count_instances(&cards, &CopyRule::Wraparound);
+
+        // Card 2 copies card 3 forwards, then both instances of card 3 wrap
+        // onto cards 1, 2 and 3.
+        assert_eq!(Some(vec![3, 3, 4]), instances);
+    }
+
+    #[test]
+    fn should_wrap_copies_for_several_laps() {
+        let cards = cards(&[0, 0, 7]);
+
+        let instances = count_instances(&cards, &CopyRule::Wraparound);
+
+        assert_eq!(Some(vec![4, 3, 3]), instances);
+    }
+
+    #[test]
+    fn should_trace_example_cards() {
+        let cards = parse_cards(include_str!("../resources/test.txt")).unwrap();
+
+        let traces = trace_cards(&cards, &CopyRule::NextN).unwrap();
+
+        let held: Vec<u64> = traces.iter().map(|trace| trace.held).collect();
+        let generated: Vec<u64> = traces.iter().map(|trace| trace.copies_generated).collect();
+        assert_eq!(vec![1, 2, 4, 8, 14, 1], held);
+        assert_eq!(vec![4, 4, 8, 8, 0, 0], generated);
+        assert_eq!(vec![(2, 5)], traces[0].copied);
+        assert_eq!(vec![(5, 5)], traces[3].copied);
+        assert!(traces[4].copied.is_empty());
+    }
+
+    #[test]
+    fn should_trace_wrapped_copies() {
+        let cards = cards(&[0, 0, 4]);
+
+        let traces = trace_cards(&cards, &CopyRule::Wraparound).unwrap();
+
+        // The wrapped copy of card 3 is held but not scratched, so only the
+        // original generates copies.
+        assert_eq!(vec![(1, 3), (1, 1)], traces[2].copied);
+        assert_eq!(2, traces[2].held);
+        assert_eq!(4, traces[2].copies_generated);
+    }
+
+    #[test]
+    fn should_format_trace_table() {
+        let traces = vec![
+            CardTrace {
+                id: 1,
+                held: 1,
+                copies_generated: 2,
+                copied: vec![(2, 3)],
+            },
+            CardTrace {
+                id: 2,
+                held: 2,
+                copies_generated: 2,
+                copied: vec![(3, 3)],
+            },
+            CardTrace {
+                id: 3,
+                held: 4,
+                copies_generated: 0,
+                copied: vec![],
+            },
+        ];
+
+        let expected = [
+            "  Card         Held    Generated  Copied",
+            "     1            1            2  2-3",
+            "     2            2            2  3",
+            "     3            4            0  -",
+            "",
+        ];
+        assert_eq!(expected.join("\n"), format_traces(&traces));
+
+        let expected = [
+            "Instances held",
+            "     1 | ## 1",
+            "     2 | #### 2",
+            "     3 | ######## 4",
+            "",
+        ];
+        assert_eq!(expected.join("\n"), format_histogram(&traces, 8));
+    }
 }
@@ -1,45 +1,316 @@
-use std::{collections::HashMap, fs};
+use std::{collections::HashMap, fmt, fs, ops::Range, process};
 
+#[derive(Debug, PartialEq, Eq)]
+enum AlmanacError {
+    OverlappingMappings {
+        from: String,
+        to: String,
+        first: Range<u64>,
+        second: Range<u64>,
+    },
+    UnknownResource(String),
+    Unreachable {
+        from: String,
+        to: String,
+    },
+    AmbiguousPath {
+        from: String,
+        to: String,
+    },
+    Cycle(Vec<String>),
+    UnpairedSeed(u64),
+    MappingOverflow {
+        from: String,
+        to: String,
+        mapping: Mapping,
+    },
+}
+
+impl fmt::Display for AlmanacError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AlmanacError::OverlappingMappings {
+                from,
+                to,
+                first,
+                second,
+            } => write!(
+                f,
+                "{from}-to-{to} map has overlapping source ranges {first:?} and {second:?}"
+            ),
+            AlmanacError::UnknownResource(name) => write!(f, "unknown resource type: {name}"),
+            AlmanacError::Unreachable { from, to } => {
+                write!(f, "no chain of maps leads from {from} to {to}")
+            }
+            AlmanacError::AmbiguousPath { from, to } => {
+                write!(f, "more than one chain of maps leads from {from} to {to}")
+            }
+            AlmanacError::Cycle(cycle) => {
+                write!(f, "resource types form a cycle: {}", cycle.join(" -> "))
+            }
+            AlmanacError::UnpairedSeed(seed) => {
+                write!(f, "seed {seed} has no range length to pair with")
+            }
+            AlmanacError::MappingOverflow { from, to, mapping } => write!(
+                f,
+                "{from}-to-{to} map row '{mapping}' runs past the largest supported value"
+            ),
+        }
+    }
+}
+
+// The resource types form a directed graph with one edge per map, keyed here by
+// the type each map converts from.
 #[derive(Debug, PartialEq, Eq)]
 struct Almanac {
-    resource_maps: HashMap<String, ResourceMap>,
+    resource_maps: HashMap<String, Vec<ResourceMap>>,
+}
+
+// How many chains of maps lead from a resource type to the target (counting no
+// further than two), along with the first one found.
+#[derive(Clone)]
+struct Route<'a> {
+    count: usize,
+    maps: Vec<&'a ResourceMap>,
 }
 
 impl Almanac {
     fn parse(rows: &mut dyn Iterator<Item = &str>) -> Almanac {
-        let mut resource_maps: HashMap<String, ResourceMap> = HashMap::new();
+        let mut resource_maps: HashMap<String, Vec<ResourceMap>> = HashMap::new();
         while let Some(header) = rows.next() {
             let mut resource_map_rows = rows.take_while(|row| !row.is_empty());
             let resource_map = ResourceMap::parse(header, &mut resource_map_rows);
-            resource_maps.insert(resource_map.from.to_string(), resource_map);
+            resource_maps
+                .entry(resource_map.from.to_string())
+                .or_default()
+                .push(resource_map);
         }
         Almanac { resource_maps }
     }
 
-    fn find_location(&self, resource_type: &String, source_value: u64) -> u64 {
-        let map = self.resource_maps.get(resource_type).unwrap();
-        let destination_value = map.get(source_value);
-        let destination_resource_type = &map.to;
-        if destination_resource_type == "location" {
-            destination_value
-        } else {
-            self.find_location(destination_resource_type, destination_value)
+    fn validate(&self) -> Result<(), AlmanacError> {
+        self.resource_maps
+            .values()
+            .flatten()
+            .try_for_each(|resource_map| resource_map.validate())
+    }
+
+    fn has_resource(&self, name: &str) -> bool {
+        self.resource_maps
+            .values()
+            .flatten()
+            .any(|resource_map| resource_map.from == name || resource_map.to == name)
+    }
+
+    // The unique chain of maps converting one resource type into another.
+    fn path(&self, from: &str, to: &str) -> Result<Vec<&ResourceMap>, AlmanacError> {
+        if let Some(name) = [from, to].into_iter().find(|name| !self.has_resource(name)) {
+            return Err(AlmanacError::UnknownResource(name.to_string()));
+        }
+        let route = self.route(from, to, &mut vec![], &mut HashMap::new())?;
+        match route.count {
+            0 => Err(AlmanacError::Unreachable {
+                from: from.to_string(),
+                to: to.to_string(),
+            }),
+            1 => Ok(route.maps),
+            _ => Err(AlmanacError::AmbiguousPath {
+                from: from.to_string(),
+                to: to.to_string(),
+            }),
         }
     }
+
+    // Depth-first search from `node`, memoising the route found from each type.
+    // Meeting a type that is still being visited means the maps form a cycle.
+    fn route<'a>(
+        &'a self,
+        node: &str,
+        to: &str,
+        visiting: &mut Vec<String>,
+        routes: &mut HashMap<String, Route<'a>>,
+    ) -> Result<Route<'a>, AlmanacError> {
+        if node == to {
+            return Ok(Route {
+                count: 1,
+                maps: vec![],
+            });
+        }
+        if let Some(route) = routes.get(node) {
+            return Ok(route.clone());
+        }
+        if let Some(start) = visiting.iter().position(|name| *name == node) {
+            let mut cycle: Vec<String> = visiting[start..].to_vec();
+            cycle.push(node.to_string());
+            return Err(AlmanacError::Cycle(cycle));
+        }
+
+        visiting.push(node.to_string());
+        let mut route = Route {
+            count: 0,
+            maps: vec![],
+        };
+        for resource_map in self.resource_maps.get(node).into_iter().flatten() {
+            let next = self.route(&resource_map.to, to, visiting, routes)?;
+            if next.count == 0 {
+                continue;
+            }
+            if route.count == 0 {
+                route.maps = std::iter::once(resource_map).chain(next.maps).collect();
+            }
+            route.count = (route.count + next.count).min(2);
+        }
+        visiting.pop();
+        routes.insert(node.to_string(), route.clone());
+        Ok(route)
+    }
+
+    // Flattens the chain of maps leading from one resource type to another into
+    // a single map, so that each lookup is a single binary search.
+    fn compose_chain(&self, from: &str, to: &str) -> Result<ResourceMap, AlmanacError> {
+        let identity = ResourceMap::new(from.to_string(), from.to_string(), vec![]);
+        self.path(from, to)?
+            .into_iter()
+            .try_fold(identity, |composed, next| compose(&composed, next))
+    }
+
+    // Follows a value through each map on the way from one resource type to
+    // another, recording which mapping applied at every stage.
+    fn trace(&self, from: &str, to: &str, value: u64) -> Result<Vec<TraceStep<'_>>, AlmanacError> {
+        let mut input = value;
+        let steps = self
+            .path(from, to)?
+            .into_iter()
+            .map(|resource_map| {
+                let mapping = resource_map.find(input);
+                let output = mapping
+                    .and_then(|mapping| mapping.get(input))
+                    .unwrap_or(input);
+                let step = TraceStep {
+                    from: &resource_map.from,
+                    to: &resource_map.to,
+                    input,
+                    mapping,
+                    output,
+                };
+                input = output;
+                step
+            })
+            .collect();
+        Ok(steps)
+    }
 }
 
 #[derive(Debug, PartialEq, Eq)]
+struct TraceStep<'a> {
+    from: &'a str,
+    to: &'a str,
+    input: u64,
+    // None when no mapping covers the input and it passes through unchanged
+    mapping: Option<&'a Mapping>,
+    output: u64,
+}
+
+impl TraceStep<'_> {
+    fn to_json(&self) -> String {
+        let mapping = match self.mapping {
+            Some(mapping) => format!(
+                "{{\"dest_from\":{},\"source_from\":{},\"length\":{}}}",
+                mapping.dest_from, mapping.source_from, mapping.length
+            ),
+            None => "null".to_string(),
+        };
+        format!(
+            "{{\"from\":{},\"to\":{},\"input\":{},\"mapping\":{},\"output\":{}}}",
+            json_string(self.from),
+            json_string(self.to),
+            self.input,
+            mapping,
+            self.output
+        )
+    }
+}
+
+impl fmt::Display for TraceStep<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mapping = match self.mapping {
+            Some(mapping) => mapping.to_string(),
+            None => "identity".to_string(),
+        };
+        write!(
+            f,
+            "{} {} -> {} {} (via {})",
+            self.from, self.input, self.to, self.output, mapping
+        )
+    }
+}
+
+fn json_string(value: &str) -> String {
+    let mut escaped = String::from('"');
+    for character in value.chars() {
+        match character {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            c if c.is_control() => escaped.push_str(&format!("\\u{:04x}", c as u32)),
+            c => escaped.push(c),
+        }
+    }
+    escaped.push('"');
+    escaped
+}
+
+fn xml_escape(value: &str) -> String {
+    let mut escaped = String::new();
+    for character in value.chars() {
+        match character {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
 struct Mapping {
     source_from: u64,
     dest_from: u64,
     length: u64,
 }
 
+// Written the same way as in the almanac: destination start, source start, length
+impl fmt::Display for Mapping {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {} {}", self.dest_from, self.source_from, self.length)
+    }
+}
+
 impl Mapping {
+    fn source_end(&self) -> u64 {
+        self.source_from + self.length
+    }
+
+    fn offset(&self, source: u64) -> u64 {
+        self.dest_from + (source - self.source_from)
+    }
+
+    // Whether either end of the mapping lies past u64::MAX, which would make
+    // source_end and offset overflow.
+    fn overflows(&self) -> bool {
+        self.source_from.checked_add(self.length).is_none()
+            || self.dest_from.checked_add(self.length).is_none()
+    }
+
+    fn is_identity(&self) -> bool {
+        self.source_from == self.dest_from
+    }
+
     fn get(&self, source: u64) -> Option<u64> {
-        if source > self.source_from && source < (self.source_from + self.length) {
-            let steps = source.abs_diff(self.source_from);
-            Some(self.dest_from + steps)
+        if source >= self.source_from && source < self.source_end() {
+            Some(self.offset(source))
         } else {
             None
         }
@@ -79,40 +350,633 @@ impl ResourceMap {
             };
             mappings.push(mapping);
         }
+        ResourceMap::new(from, to, mappings)
+    }
+
+    // Mappings are kept sorted by source so that lookups can binary search.
+    // Empty mappings cover nothing, and one nested inside another range would
+    // break the ordering of source ends that the search relies on, so drop them.
+    fn new(from: String, to: String, mut mappings: Vec<Mapping>) -> ResourceMap {
+        mappings.retain(|mapping| mapping.length > 0);
+        mappings.sort_by_key(|mapping| (mapping.source_from, mapping.length));
         ResourceMap { from, to, mappings }
     }
 
+    // A source value must be covered by at most one mapping, otherwise the
+    // result of get depends on the order the mappings are listed in.
+    fn validate(&self) -> Result<(), AlmanacError> {
+        if let Some(mapping) = self.mappings.iter().find(|mapping| mapping.overflows()) {
+            return Err(self.overflow(mapping));
+        }
+        let mut sources: Vec<Range<u64>> = self
+            .mappings
+            .iter()
+            .map(|mapping| mapping.source_from..mapping.source_end())
+            .collect();
+        sources.sort_by_key(|range| range.start);
+        match sources.windows(2).find(|pair| pair[1].start < pair[0].end) {
+            Some(pair) => Err(AlmanacError::OverlappingMappings {
+                from: self.from.clone(),
+                to: self.to.clone(),
+                first: pair[0].clone(),
+                second: pair[1].clone(),
+            }),
+            None => Ok(()),
+        }
+    }
+
+    fn overflow(&self, mapping: &Mapping) -> AlmanacError {
+        AlmanacError::MappingOverflow {
+            from: self.from.clone(),
+            to: self.to.clone(),
+            mapping: mapping.clone(),
+        }
+    }
+
     fn get(&self, source: u64) -> u64 {
+        self.find(source)
+            .and_then(|mapping| mapping.get(source))
+            .unwrap_or(source)
+    }
+
+    // The mapping covering a source value, if any
+    fn find(&self, source: u64) -> Option<&Mapping> {
+        let index = self
+            .mappings
+            .partition_point(|mapping| mapping.source_end() <= source);
+        self.mappings
+            .get(index)
+            .filter(|mapping| mapping.get(source).is_some())
+    }
+
+    // Every source value that maps to the given value. Only meaningful on an
+    // inverted map, whose mappings may overlap and which has no identity fallback.
+    fn get_all(&self, source: u64) -> Vec<u64> {
         self.mappings
             .iter()
-            .find(|mapping| mapping.get(source).is_some())
-            .map(|mapping| mapping.get(source).unwrap())
-            .unwrap_or(source)
+            .filter_map(|mapping| mapping.get(source))
+            .collect()
+    }
+
+    // The mappings with the identity gaps between them filled in explicitly, so
+    // that together they cover every source value below u64::MAX.
+    fn segments(&self) -> Vec<Mapping> {
+        let mut segments: Vec<Mapping> = vec![];
+        let mut cursor = 0;
+        for mapping in self.mappings.iter() {
+            if mapping.source_from > cursor {
+                segments.push(Mapping {
+                    source_from: cursor,
+                    dest_from: cursor,
+                    length: mapping.source_from - cursor,
+                });
+            }
+            segments.push(mapping.clone());
+            cursor = mapping.source_end();
+        }
+        if cursor < u64::MAX {
+            segments.push(Mapping {
+                source_from: cursor,
+                dest_from: cursor,
+                length: u64::MAX - cursor,
+            });
+        }
+        segments
+    }
+
+    // Maps destination values back to source values. Identity gaps become explicit
+    // mappings, and several sources can reach the same destination, so look values
+    // up with get_all rather than get.
+    fn invert(&self) -> ResourceMap {
+        let mappings = self
+            .segments()
+            .into_iter()
+            .map(|segment| Mapping {
+                source_from: segment.dest_from,
+                dest_from: segment.source_from,
+                length: segment.length,
+            })
+            .collect();
+        ResourceMap::new(self.to.clone(), self.from.clone(), mappings)
+    }
+
+    // Maps each half-open source range to the destination ranges it covers,
+    // splitting it wherever a mapping starts or ends. Parts not covered by any
+    // mapping are passed through unchanged. The result is sorted and merged.
+    fn get_ranges(&self, ranges: &[Range<u64>]) -> Vec<Range<u64>> {
+        let mappings = &self.mappings;
+        let mut destination_ranges: Vec<Range<u64>> = vec![];
+        for range in ranges.iter().filter(|range| !range.is_empty()) {
+            let mut cursor = range.start;
+            let first = mappings.partition_point(|mapping| mapping.source_end() <= cursor);
+            for mapping in mappings[first..].iter() {
+                if mapping.source_from >= range.end {
+                    break;
+                }
+                if mapping.source_from > cursor {
+                    destination_ranges.push(cursor..mapping.source_from);
+                    cursor = mapping.source_from;
+                }
+                let until = mapping.source_end().min(range.end);
+                if until > cursor {
+                    destination_ranges.push(mapping.offset(cursor)..mapping.offset(until));
+                    cursor = until;
+                }
+            }
+            if cursor < range.end {
+                destination_ranges.push(cursor..range.end);
+            }
+        }
+        merge_ranges(destination_ranges)
     }
 }
 
+// Flattens two maps, where the first's destination is the second's source, into
+// a single piecewise map from the first's source to the second's destination.
+fn compose(first: &ResourceMap, second: &ResourceMap) -> Result<ResourceMap, AlmanacError> {
+    assert_eq!(first.to, second.from, "maps do not chain");
+    let second_segments = second.segments();
+    let mut mappings: Vec<Mapping> = vec![];
+    for segment in first.segments() {
+        let dest_end = segment
+            .dest_from
+            .checked_add(segment.length)
+            .ok_or_else(|| first.overflow(&segment))?;
+        let start = second_segments.partition_point(|next| next.source_end() <= segment.dest_from);
+        for next in second_segments[start..].iter() {
+            if next.source_from >= dest_end {
+                break;
+            }
+            let from = next.source_from.max(segment.dest_from);
+            let until = next.source_end().min(dest_end);
+            mappings.push(Mapping {
+                source_from: segment.source_from + (from - segment.dest_from),
+                dest_from: next.offset(from),
+                length: until - from,
+            });
+        }
+    }
+
+    // Merge neighbouring pieces that share an offset, and leave identity pieces
+    // to the fallback in get.
+    let mut simplified: Vec<Mapping> = vec![];
+    for mapping in mappings {
+        match simplified.last_mut() {
+            Some(last)
+                if last.source_end() == mapping.source_from
+                    && last.dest_from.checked_add(last.length) == Some(mapping.dest_from) =>
+            {
+                last.length += mapping.length
+            }
+            _ => simplified.push(mapping),
+        }
+    }
+    simplified.retain(|mapping| !mapping.is_identity());
+    Ok(ResourceMap::new(
+        first.from.clone(),
+        second.to.clone(),
+        simplified,
+    ))
+}
+
+const CHART_SIZE: f64 = 400.0;
+const CHART_MARGIN: f64 = 40.0;
+
+// Draws a map as a piecewise-linear chart of source (x) against destination (y).
+// Mapped segments are drawn in blue and identity stretches in grey, with the
+// axes sized to fit every mapped segment and highlighted source range.
+// `minimum`, a (source, destination) point, is marked and labelled.
+fn render_svg(map: &ResourceMap, highlights: &[Range<u64>], minimum: Option<(u64, u64)>) -> String {
+    let extent = map
+        .mappings
+        .iter()
+        .flat_map(|mapping| [mapping.source_end(), mapping.dest_from + mapping.length])
+        .chain(highlights.iter().map(|range| range.end))
+        .chain(minimum.iter().flat_map(|(source, dest)| [*source, *dest]))
+        .max()
+        .unwrap_or(0)
+        .max(1);
+    let round = |pixels: f64| (pixels * 100.0).round() / 100.0;
+    let scale = |value: u64| value as f64 / extent as f64 * CHART_SIZE;
+    let x = |value: u64| round(CHART_MARGIN + scale(value));
+    let y = |value: u64| round(CHART_MARGIN + CHART_SIZE - scale(value));
+    let size = CHART_SIZE + 2.0 * CHART_MARGIN;
+    let (from, to) = (xml_escape(&map.from), xml_escape(&map.to));
+
+    let mut svg = vec![
+        format!(
+            r#"<svg xmlns="http://www.w3.org/2000/svg" width="{size}" height="{size}" viewBox="0 0 {size} {size}">"#
+        ),
+        format!("<title>{from}-to-{to}</title>"),
+        format!(
+            r#"<rect x="{CHART_MARGIN}" y="{CHART_MARGIN}" width="{CHART_SIZE}" height="{CHART_SIZE}" fill="none" stroke="black"/>"#
+        ),
+        format!(
+            r#"<text x="{}" y="{}" text-anchor="middle">{from}</text>"#,
+            size / 2.0,
+            size - 8.0
+        ),
+        format!(
+            r#"<text x="12" y="{}" text-anchor="middle" transform="rotate(-90 12 {})">{to}</text>"#,
+            size / 2.0,
+            size / 2.0
+        ),
+        format!(
+            r#"<text x="{}" y="{}" text-anchor="end">{extent}</text>"#,
+            x(extent),
+            size - 24.0
+        ),
+    ];
+    for range in highlights {
+        let until = range.end.min(extent);
+        svg.push(format!(
+            r#"<rect x="{}" y="{CHART_MARGIN}" width="{}" height="{CHART_SIZE}" fill="orange" fill-opacity="0.25"/>"#,
+            x(range.start),
+            x(until) - x(range.start)
+        ));
+    }
+    for segment in map.segments() {
+        if segment.source_from >= extent {
+            break;
+        }
+        let length = segment.length.min(extent - segment.source_from);
+        let colour = if segment.is_identity() {
+            "lightgray"
+        } else {
+            "steelblue"
+        };
+        svg.push(format!(
+            r#"<line x1="{}" y1="{}" x2="{}" y2="{}" stroke="{colour}" stroke-width="2"/>"#,
+            x(segment.source_from),
+            y(segment.dest_from),
+            x(segment.source_from + length),
+            y(segment.dest_from + length)
+        ));
+    }
+    if let Some((source, dest)) = minimum {
+        svg.push(format!(
+            r#"<circle cx="{}" cy="{}" r="4" fill="red"/>"#,
+            x(source),
+            y(dest)
+        ));
+        svg.push(format!(
+            r#"<text x="{}" y="{}" fill="red">min {to} {dest} at {from} {source}</text>"#,
+            x(source) + 6.0,
+            y(dest) - 6.0
+        ));
+    }
+    svg.push("</svg>".to_string());
+    svg.join("\n") + "\n"
+}
+
+fn merge_ranges(mut ranges: Vec<Range<u64>>) -> Vec<Range<u64>> {
+    ranges.sort_by_key(|range| range.start);
+    let mut merged: Vec<Range<u64>> = vec![];
+    for range in ranges {
+        match merged.last_mut() {
+            Some(last) if range.start <= last.end => last.end = last.end.max(range.end),
+            _ => merged.push(range),
+        }
+    }
+    merged
+}
+
 fn main() {
     let args: Vec<String> = std::env::args().collect();
     let file_name = args.get(1).unwrap();
 
     let file = fs::read_to_string(file_name).unwrap();
     let mut lines = file.lines();
-    let seeds = lines.next().unwrap();
+    let seeds = parse_seeds(lines.next().unwrap());
     lines.next(); // Skip blank line
     let almanac = Almanac::parse(&mut lines);
+    if let Err(err) = almanac.validate() {
+        eprintln!("Invalid almanac in {file_name}: {err}");
+        process::exit(1);
+    }
 
-    let lowest_location = seeds[7..]
-        .split(' ')
-        .map(|num| num.parse().unwrap())
-        .map(|seed_value| almanac.find_location(&"seed".to_string(), seed_value))
+    let from = flag(&args, "--from=").unwrap_or("seed");
+    let to = flag(&args, "--to=").unwrap_or("location");
+    let seed_to_location = almanac.compose_chain(from, to).unwrap_or_else(|err| {
+        eprintln!("Invalid almanac in {file_name}: {err}");
+        process::exit(1);
+    });
+
+    let lowest_location = seeds
+        .iter()
+        .map(|seed_value| seed_to_location.get(*seed_value))
         .min()
         .unwrap();
     println!("Part 1: {}", lowest_location);
+
+    let seed_ranges = seed_ranges(&seeds).unwrap_or_else(|err| {
+        eprintln!("Invalid almanac in {file_name}: {err}");
+        process::exit(1);
+    });
+    let lowest_location = seed_to_location
+        .get_ranges(&seed_ranges)
+        .first()
+        .unwrap()
+        .start;
+    println!("Part 2: {}", lowest_location);
+
+    if let Some(location) = number_flag(&args, "--reverse=") {
+        let seeds = seed_to_location.invert().get_all(location);
+        println!("Seeds reaching location {location}: {seeds:?}");
+    }
+
+    if let Some(value) = number_flag(&args, "--trace=") {
+        let steps = almanac.trace(from, to, value).unwrap_or_else(|err| {
+            eprintln!("Invalid almanac in {file_name}: {err}");
+            process::exit(1);
+        });
+        if args.iter().any(|arg| arg == "--json") {
+            let steps: Vec<String> = steps.iter().map(|step| step.to_json()).collect();
+            println!("[{}]", steps.join(","));
+        } else {
+            steps.iter().for_each(|step| println!("{step}"));
+        }
+    }
+
+    if let Some(directory) = flag(&args, "--svg=") {
+        let directory = std::path::Path::new(directory);
+        fs::create_dir_all(directory).unwrap();
+        for resource_map in almanac.resource_maps.values().flatten() {
+            let file_name = format!("{}-to-{}.svg", resource_map.from, resource_map.to);
+            fs::write(
+                directory.join(file_name),
+                render_svg(resource_map, &[], None),
+            )
+            .unwrap();
+        }
+        let lowest_seed = seed_to_location
+            .invert()
+            .get_all(lowest_location)
+            .into_iter()
+            .filter(|seed| seed_ranges.iter().any(|range| range.contains(seed)))
+            .min()
+            .unwrap();
+        let chart = render_svg(
+            &seed_to_location,
+            &seed_ranges,
+            Some((lowest_seed, lowest_location)),
+        );
+        let file_name = format!("{}-to-{}.svg", seed_to_location.from, seed_to_location.to);
+        fs::write(directory.join(file_name), chart).unwrap();
+    }
+}
+
+fn flag<'a>(args: &'a [String], prefix: &str) -> Option<&'a str> {
+    args.iter().find_map(|arg| arg.strip_prefix(prefix))
+}
+
+fn number_flag(args: &[String], prefix: &str) -> Option<u64> {
+    flag(args, prefix).map(|value| match value.parse() {
+        Ok(number) => number,
+        Err(_) => {
+            let name = prefix.trim_end_matches('=');
+            eprintln!("{name} needs a number, got '{value}'");
+            process::exit(1);
+        }
+    })
+}
+
+fn parse_seeds(row: &str) -> Vec<u64> {
+    row.trim_start_matches("seeds:")
+        .split_ascii_whitespace()
+        .map(|num| num.parse().unwrap())
+        .collect()
+}
+
+// Seeds come in (start, length) pairs. Ranges running past u64::MAX are cut
+// short there rather than wrapping around.
+fn seed_ranges(seeds: &[u64]) -> Result<Vec<Range<u64>>, AlmanacError> {
+    let pairs = seeds.chunks_exact(2);
+    if let [seed] = pairs.remainder() {
+        return Err(AlmanacError::UnpairedSeed(*seed));
+    }
+    Ok(pairs
+        .map(|pair| pair[0]..pair[0].saturating_add(pair[1]))
+        .collect())
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use proptest::prelude::*;
+
+    // Non-overlapping mappings, listed in a shuffled order, with small lengths so
+    // that a brute-force table of every mapped value stays cheap to build.
+    fn mappings() -> impl Strategy<Value = Vec<Mapping>> {
+        prop::collection::vec((0..20u64, 0..20u64, 0..200u64), 0..8)
+            .prop_map(|segments| {
+                let mut source_from = 0;
+                let mut mappings = vec![];
+                for (gap, length, dest_from) in segments {
+                    source_from += gap;
+                    mappings.push(Mapping {
+                        source_from,
+                        dest_from,
+                        length,
+                    });
+                    source_from += length;
+                }
+                mappings
+            })
+            .prop_shuffle()
+    }
+
+    fn resource_map(mappings: Vec<Mapping>) -> ResourceMap {
+        ResourceMap::new("seed".into(), "soil".into(), mappings)
+    }
+
+    fn brute_force(mappings: &[Mapping]) -> HashMap<u64, u64> {
+        let mut table = HashMap::new();
+        for mapping in mappings {
+            for step in 0..mapping.length {
+                table.insert(mapping.source_from + step, mapping.dest_from + step);
+            }
+        }
+        table
+    }
+
+    proptest! {
+        #[test]
+        fn should_match_brute_force_lookup(mappings in mappings()) {
+            let table = brute_force(&mappings);
+            let map = resource_map(mappings);
+
+            for source in 0..250 {
+                let expected = *table.get(&source).unwrap_or(&source);
+                prop_assert_eq!(expected, map.get(source));
+            }
+        }
+
+        #[test]
+        fn should_map_ranges_like_individual_values(
+            mappings in mappings(),
+            start in 0..200u64,
+            length in 0..60u64,
+        ) {
+            let map = resource_map(mappings);
+
+            let range = start..start + length;
+            let ranges = map.get_ranges(std::slice::from_ref(&range));
+
+            let expected = merge_ranges(range.map(|source| {
+                let destination = map.get(source);
+                destination..destination + 1
+            }).collect());
+            prop_assert_eq!(expected, ranges);
+        }
+
+        #[test]
+        fn should_compose_maps(first in mappings(), second in mappings()) {
+            let first = resource_map(first);
+            let second = ResourceMap::new("soil".into(), "water".into(), second);
+
+            let composed = compose(&first, &second).unwrap();
+
+            prop_assert_eq!(Ok(()), composed.validate());
+            for source in 0..250 {
+                prop_assert_eq!(second.get(first.get(source)), composed.get(source));
+            }
+        }
+
+        #[test]
+        fn should_invert_map(mappings in mappings()) {
+            let map = resource_map(mappings);
+
+            let inverted = map.invert();
+
+            for source in 0..250 {
+                let destination = map.get(source);
+                let sources = inverted.get_all(destination);
+                prop_assert!(sources.contains(&source));
+                prop_assert!(sources.iter().all(|source| map.get(*source) == destination));
+            }
+        }
+
+        #[test]
+        fn should_accept_non_overlapping_mappings(mappings in mappings()) {
+            prop_assert_eq!(Ok(()), resource_map(mappings).validate());
+        }
+
+        #[test]
+        fn should_detect_overlapping_mappings(
+            mappings in mappings(),
+            index in any::<prop::sample::Index>(),
+            offset in 0..20u64,
+        ) {
+            let mut mappings: Vec<Mapping> = mappings
+                .into_iter()
+                .filter(|mapping| mapping.length > 0)
+                .collect();
+            prop_assume!(!mappings.is_empty());
+            let overlapped = &mappings[index.index(mappings.len())];
+            let source_from = overlapped.source_from + offset % overlapped.length;
+            mappings.push(Mapping {
+                source_from,
+                dest_from: 0,
+                length: 1,
+            });
+
+            let result = resource_map(mappings).validate();
+
+            prop_assert!(
+                matches!(result, Err(AlmanacError::OverlappingMappings { .. })),
+                "expected overlap to be reported"
+            );
+        }
+    }
+
+    #[test]
+    fn should_map_first_value_of_range() {
+        let mapping = Mapping {
+            source_from: 98,
+            dest_from: 50,
+            length: 2,
+        };
+
+        assert_eq!(None, mapping.get(97));
+        assert_eq!(Some(50), mapping.get(98));
+        assert_eq!(Some(51), mapping.get(99));
+        assert_eq!(None, mapping.get(100));
+    }
+
+    #[test]
+    fn should_ignore_empty_mapping_nested_in_another() {
+        let map = resource_map(vec![
+            Mapping {
+                source_from: 5,
+                dest_from: 100,
+                length: 15,
+            },
+            Mapping {
+                source_from: 10,
+                dest_from: 0,
+                length: 0,
+            },
+        ]);
+
+        assert_eq!(Ok(()), map.validate());
+        assert_eq!(110, map.get(15));
+        let range = 13..17;
+        assert_eq!(vec![108..112], map.get_ranges(std::slice::from_ref(&range)));
+    }
+
+    #[test]
+    fn should_report_overlapping_mappings() {
+        let map = resource_map(vec![
+            Mapping {
+                source_from: 10,
+                dest_from: 0,
+                length: 5,
+            },
+            Mapping {
+                source_from: 14,
+                dest_from: 50,
+                length: 3,
+            },
+        ]);
+
+        let expected = AlmanacError::OverlappingMappings {
+            from: "seed".into(),
+            to: "soil".into(),
+            first: 10..15,
+            second: 14..17,
+        };
+        assert_eq!(Err(expected), map.validate());
+    }
+
+    #[test]
+    fn should_report_mappings_past_largest_value() {
+        let past_source = Mapping {
+            source_from: u64::MAX - 5,
+            dest_from: 0,
+            length: 10,
+        };
+        let past_dest = Mapping {
+            source_from: 0,
+            dest_from: u64::MAX - 5,
+            length: 10,
+        };
+        let overflow = |mapping: &Mapping| AlmanacError::MappingOverflow {
+            from: "seed".into(),
+            to: "soil".into(),
+            mapping: mapping.clone(),
+        };
+
+        let first = resource_map(vec![past_dest.clone()]);
+        let second = ResourceMap::new("soil".into(), "water".into(), vec![]);
+
+        assert_eq!(
+            Err(overflow(&past_source)),
+            resource_map(vec![past_source.clone()]).validate()
+        );
+        assert_eq!(Err(overflow(&past_dest)), first.validate());
+        assert_eq!(Err(overflow(&past_dest)), compose(&first, &second));
+    }
 
     #[test]
     fn should_parse_range() {
@@ -125,16 +989,16 @@ mod tests {
             from: "seed".into(),
             to: "soil".into(),
             mappings: vec![
-                Mapping {
-                    source_from: 98,
-                    dest_from: 50,
-                    length: 2,
-                },
                 Mapping {
                     source_from: 50,
                     dest_from: 52,
                     length: 3,
                 },
+                Mapping {
+                    source_from: 98,
+                    dest_from: 50,
+                    length: 2,
+                },
             ],
         };
         assert_eq!(expected_map, map);
@@ -158,26 +1022,26 @@ mod tests {
             resource_maps: HashMap::from([
                 (
                     "seed".into(),
-                    ResourceMap {
+                    vec![ResourceMap {
                         from: "seed".into(),
                         to: "soil".into(),
                         mappings: vec![
-                            Mapping {
-                                source_from: 98,
-                                dest_from: 50,
-                                length: 2,
-                            },
                             Mapping {
                                 source_from: 50,
                                 dest_from: 52,
                                 length: 3,
                             },
+                            Mapping {
+                                source_from: 98,
+                                dest_from: 50,
+                                length: 2,
+                            },
                         ],
-                    },
+                    }],
                 ),
                 (
                     "soil".into(),
-                    ResourceMap {
+                    vec![ResourceMap {
                         from: "soil".into(),
                         to: "fertilizer".into(),
                         mappings: vec![
@@ -192,10 +1056,252 @@ mod tests {
                                 length: 2,
                             },
                         ],
-                    },
+                    }],
                 ),
             ]),
         };
         assert_eq!(expected_almanac, almanac);
     }
+
+    #[test]
+    fn should_split_ranges_at_mapping_boundaries() {
+        let map = resource_map(vec![
+            Mapping {
+                source_from: 98,
+                dest_from: 50,
+                length: 2,
+            },
+            Mapping {
+                source_from: 50,
+                dest_from: 52,
+                length: 48,
+            },
+        ]);
+
+        let ranges = map.get_ranges(&[40..55, 97..102]);
+
+        // 40..50 and 100..102 are unmapped, 50..55 and 97 shift up by two and
+        // 98..100 moves down to 50..52, after which adjacent ranges merge.
+        assert_eq!(vec![40..57, 99..102], ranges);
+    }
+
+    #[test]
+    fn should_pass_through_unmapped_ranges() {
+        let map = resource_map(vec![]);
+
+        assert_eq!(vec![3..8], map.get_ranges(&[5..8, 3..5, 6..6]));
+    }
+
+    #[test]
+    fn should_find_lowest_location_for_seed_ranges() {
+        let file = include_str!("../resources/test.txt");
+        let mut lines = file.lines();
+        let seeds = parse_seeds(lines.next().unwrap());
+        lines.next();
+        let almanac = Almanac::parse(&mut lines);
+
+        let seed_to_location = almanac.compose_chain("seed", "location").unwrap();
+
+        let locations = seed_to_location.get_ranges(&seed_ranges(&seeds).unwrap());
+        assert_eq!(46, locations.first().unwrap().start);
+        let locations: Vec<u64> = seeds
+            .iter()
+            .map(|seed| seed_to_location.get(*seed))
+            .collect();
+        assert_eq!(vec![82, 43, 86, 35], locations);
+        assert_eq!(vec![13], seed_to_location.invert().get_all(35));
+    }
+
+    #[test]
+    fn should_pair_seeds_into_ranges() {
+        assert_eq!(
+            Ok(vec![79..93, u64::MAX - 1..u64::MAX]),
+            seed_ranges(&[79, 14, u64::MAX - 1, 5])
+        );
+        assert_eq!(
+            Err(AlmanacError::UnpairedSeed(55)),
+            seed_ranges(&[79, 14, 55])
+        );
+    }
+
+    fn graph(headers: &[&str]) -> Almanac {
+        let mut rows: Vec<&str> = vec![];
+        for header in headers {
+            rows.extend([*header, "0 10 5", ""]);
+        }
+        Almanac::parse(&mut rows.into_iter())
+    }
+
+    #[test]
+    fn should_compose_between_any_resource_types() {
+        let file = include_str!("../resources/test.txt");
+        let mut lines = file.lines().skip(2);
+        let almanac = Almanac::parse(&mut lines);
+
+        let soil_to_water = almanac.compose_chain("soil", "water").unwrap();
+
+        // Soil 53 is fertilizer 38 and water 27; soil 14 is fertilizer 53 and water 49.
+        assert_eq!(27, soil_to_water.get(53));
+        assert_eq!(49, soil_to_water.get(14));
+        assert_eq!(0, almanac.path("water", "water").unwrap().len());
+        assert_eq!(81, almanac.compose_chain("water", "water").unwrap().get(81));
+    }
+
+    #[test]
+    fn should_follow_the_branch_that_reaches_the_target() {
+        let almanac = graph(&[
+            "seed-to-soil map:",
+            "seed-to-water map:",
+            "water-to-location map:",
+        ]);
+
+        let path: Vec<String> = almanac
+            .path("seed", "location")
+            .unwrap()
+            .iter()
+            .map(|resource_map| resource_map.to.clone())
+            .collect();
+
+        assert_eq!(vec!["water".to_string(), "location".to_string()], path);
+    }
+
+    #[test]
+    fn should_report_cycles() {
+        let almanac = graph(&[
+            "seed-to-soil map:",
+            "soil-to-water map:",
+            "water-to-seed map:",
+            "location-to-seed map:",
+        ]);
+
+        let expected = AlmanacError::Cycle(vec![
+            "seed".into(),
+            "soil".into(),
+            "water".into(),
+            "seed".into(),
+        ]);
+        assert_eq!(Some(expected), almanac.path("seed", "location").err());
+    }
+
+    #[test]
+    fn should_report_unreachable_and_unknown_types() {
+        let almanac = graph(&["seed-to-soil map:", "water-to-location map:"]);
+
+        let expected = AlmanacError::Unreachable {
+            from: "seed".into(),
+            to: "location".into(),
+        };
+        assert_eq!(Some(expected), almanac.path("seed", "location").err());
+        let expected = AlmanacError::UnknownResource("light".into());
+        assert_eq!(Some(expected), almanac.path("seed", "light").err());
+    }
+
+    #[test]
+    fn should_report_ambiguous_paths() {
+        let almanac = graph(&[
+            "seed-to-soil map:",
+            "seed-to-water map:",
+            "soil-to-location map:",
+            "water-to-location map:",
+        ]);
+
+        let expected = AlmanacError::AmbiguousPath {
+            from: "seed".into(),
+            to: "location".into(),
+        };
+        assert_eq!(Some(expected), almanac.path("seed", "location").err());
+    }
+
+    #[test]
+    fn should_trace_seed_through_each_map() {
+        let file = include_str!("../resources/test.txt");
+        let mut lines = file.lines().skip(2);
+        let almanac = Almanac::parse(&mut lines);
+
+        let steps = almanac.trace("seed", "location", 79).unwrap();
+
+        let values: Vec<(&str, u64)> = steps.iter().map(|step| (step.to, step.output)).collect();
+        let expected = vec![
+            ("soil", 81),
+            ("fertilizer", 81),
+            ("water", 81),
+            ("light", 74),
+            ("temperature", 78),
+            ("humidity", 78),
+            ("location", 82),
+        ];
+        assert_eq!(expected, values);
+        assert_eq!("seed 79 -> soil 81 (via 52 50 48)", steps[0].to_string());
+        assert_eq!(
+            "soil 81 -> fertilizer 81 (via identity)",
+            steps[1].to_string()
+        );
+    }
+
+    #[test]
+    fn should_serialise_trace_step_as_json() {
+        let mapping = Mapping {
+            source_from: 50,
+            dest_from: 52,
+            length: 48,
+        };
+        let step = TraceStep {
+            from: "seed",
+            to: "soil \"A\"",
+            input: 79,
+            mapping: Some(&mapping),
+            output: 81,
+        };
+        let identity = TraceStep {
+            mapping: None,
+            ..step
+        };
+
+        assert_eq!(
+            r#"{"from":"seed","to":"soil \"A\"","input":79,"mapping":{"dest_from":52,"source_from":50,"length":48},"output":81}"#,
+            step.to_json()
+        );
+        assert_eq!(
+            r#"{"from":"seed","to":"soil \"A\"","input":79,"mapping":null,"output":81}"#,
+            identity.to_json()
+        );
+    }
+
+    #[test]
+    fn should_render_map_segments() {
+        let map = resource_map(vec![Mapping {
+            source_from: 50,
+            dest_from: 0,
+            length: 50,
+        }]);
+
+        let svg = render_svg(&map, std::slice::from_ref(&(25..50)), Some((50, 0)));
+
+        let lines: Vec<&str> = svg
+            .lines()
+            .filter(|line| line.starts_with("<line"))
+            .collect();
+        let expected = vec![
+            r#"<line x1="40" y1="440" x2="240" y2="240" stroke="lightgray" stroke-width="2"/>"#,
+            r#"<line x1="240" y1="440" x2="440" y2="240" stroke="steelblue" stroke-width="2"/>"#,
+        ];
+        assert_eq!(expected, lines);
+        assert!(svg.contains(
+            r#"<rect x="140" y="40" width="100" height="400" fill="orange" fill-opacity="0.25"/>"#
+        ));
+        assert!(svg.contains(r#"<circle cx="240" cy="440" r="4" fill="red"/>"#));
+        assert!(svg.contains("min soil 0 at seed 50</text>"));
+        assert!(svg.starts_with("<svg") && svg.ends_with("</svg>\n"));
+    }
+
+    #[test]
+    fn should_escape_resource_names_in_svg() {
+        let map = ResourceMap::new("<seed>".into(), "soil & \"water\"".into(), vec![]);
+
+        let svg = render_svg(&map, &[], Some((1, 1)));
+
+        assert!(svg.contains("<title>&lt;seed&gt;-to-soil &amp; &quot;water&quot;</title>"));
+        assert!(svg.contains("min soil &amp; &quot;water&quot; 1 at &lt;seed&gt; 1</text>"));
+        assert!(!svg.contains("<seed>"));
+    }
 }
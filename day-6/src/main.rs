@@ -1,36 +1,380 @@
-use std::fs;
+use std::{fmt::Debug, fs, process, str::FromStr};
+
+use num::{BigUint, One, Zero};
+
+// The sheet of paper has very bad kerning: the numbers on each row can be read
+// as separate races or, with the spaces ignored, as one long race.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Kerning {
+    Separate,
+    Joined,
+}
+
+// How far a boat travels given the race time and how long the button is held.
+// Every model's distance must strictly increase with hold time up to its peak
+// and never increase after it, which the default search methods rely on.
+trait BoatModel: Debug {
+    fn distance(&self, race_time: u64, hold: u64) -> u128;
+
+    fn optimal_hold(&self, race_time: u64) -> u64 {
+        search_optimal_hold(self, race_time)
+    }
+
+    // The shortest and longest hold times that beat the record, if any do
+    fn winning_holds(&self, race_time: u64, record: u64) -> Option<(u64, u64)> {
+        search_winning_holds(self, race_time, record)
+    }
+
+    fn num_solutions(&self, race_time: u64, record: u64) -> u64 {
+        match self.winning_holds(race_time, record) {
+            Some((min, max)) => max - min + 1,
+            None => 0,
+        }
+    }
+}
+
+// Speed is the hold time: the puzzle's model
+#[derive(Debug)]
+struct LinearCharge;
+
+impl BoatModel for LinearCharge {
+    fn distance(&self, race_time: u64, hold: u64) -> u128 {
+        hold as u128 * (race_time - hold) as u128
+    }
+
+    fn optimal_hold(&self, race_time: u64) -> u64 {
+        race_time / 2
+    }
+
+    fn winning_holds(&self, race_time: u64, record: u64) -> Option<(u64, u64)> {
+        solve_race(race_time, record)
+    }
+
+    fn num_solutions(&self, race_time: u64, record: u64) -> u64 {
+        num_solutions(race_time, record)
+    }
+}
+
+// Speed is the hold time multiplied by a rate
+#[derive(Debug)]
+struct Acceleration {
+    rate: u64,
+}
+
+impl BoatModel for Acceleration {
+    fn distance(&self, race_time: u64, hold: u64) -> u128 {
+        LinearCharge
+            .distance(race_time, hold)
+            .saturating_mul(self.rate as u128)
+    }
+
+    fn optimal_hold(&self, race_time: u64) -> u64 {
+        race_time / 2
+    }
+
+    // rate * x > d exactly when x > d / rate, rounding down
+    fn winning_holds(&self, race_time: u64, record: u64) -> Option<(u64, u64)> {
+        match self.rate {
+            0 => None,
+            rate => solve_race(race_time, record / rate),
+        }
+    }
+}
+
+// The boat already moves at a starting speed, and holding adds to it
+#[derive(Debug)]
+struct StartingSpeed {
+    speed: u64,
+}
+
+impl BoatModel for StartingSpeed {
+    fn distance(&self, race_time: u64, hold: u64) -> u128 {
+        (self.speed as u128 + hold as u128) * (race_time - hold) as u128
+    }
+
+    // Writing g = speed + h turns this into the linear model for a race of
+    // T + speed, restricted to g >= speed.
+    fn optimal_hold(&self, race_time: u64) -> u64 {
+        match race_time.checked_add(self.speed) {
+            Some(shifted) => (shifted / 2).saturating_sub(self.speed),
+            None => search_optimal_hold(self, race_time),
+        }
+    }
+
+    fn winning_holds(&self, race_time: u64, record: u64) -> Option<(u64, u64)> {
+        let Some(shifted) = race_time.checked_add(self.speed) else {
+            return search_winning_holds(self, race_time, record);
+        };
+        let (min, max) = solve_race(shifted, record)?;
+        if max < self.speed {
+            return None;
+        }
+        Some((min.max(self.speed) - self.speed, max - self.speed))
+    }
+}
+
+// Speed is the hold time, but never more than a maximum. There's no closed
+// form here, so it uses the default searches.
+#[derive(Debug)]
+struct SpeedCap {
+    max_speed: u64,
+}
+
+impl BoatModel for SpeedCap {
+    fn distance(&self, race_time: u64, hold: u64) -> u128 {
+        hold.min(self.max_speed) as u128 * (race_time - hold) as u128
+    }
+}
+
+fn parse_model(arg: &str) -> Result<Box<dyn BoatModel>, String> {
+    let parse_value = |value: &str| {
+        value
+            .parse::<u64>()
+            .map_err(|_| format!("invalid value in boat model: {arg}"))
+    };
+    match arg.split_once(':') {
+        None if arg == "linear" => Ok(Box::new(LinearCharge)),
+        Some(("accel", rate)) => Ok(Box::new(Acceleration {
+            rate: parse_value(rate)?,
+        })),
+        Some(("start", speed)) => Ok(Box::new(StartingSpeed {
+            speed: parse_value(speed)?,
+        })),
+        Some(("cap", max_speed)) => Ok(Box::new(SpeedCap {
+            max_speed: parse_value(max_speed)?,
+        })),
+        _ => Err(format!("unknown boat model: {arg}")),
+    }
+}
+
+// The first hold time whose successor travels no further
+fn search_optimal_hold<M: BoatModel + ?Sized>(model: &M, race_time: u64) -> u64 {
+    let (mut low, mut high) = (0, race_time);
+    while low < high {
+        let middle = low + (high - low) / 2;
+        if model.distance(race_time, middle + 1) <= model.distance(race_time, middle) {
+            high = middle;
+        } else {
+            low = middle + 1;
+        }
+    }
+    low
+}
+
+// Binary searches either side of the optimal hold time, where distance is
+// monotone, for the edges of the winning interval.
+fn search_winning_holds<M: BoatModel + ?Sized>(
+    model: &M,
+    race_time: u64,
+    record: u64,
+) -> Option<(u64, u64)> {
+    let beats = |hold: u64| model.distance(race_time, hold) > record as u128;
+    let optimal = model.optimal_hold(race_time);
+    if !beats(optimal) {
+        return None;
+    }
+
+    let (mut low, mut high) = (0, optimal);
+    while low < high {
+        let middle = low + (high - low) / 2;
+        if beats(middle) {
+            high = middle;
+        } else {
+            low = middle + 1;
+        }
+    }
+    let min = low;
+
+    let (mut low, mut high) = (optimal, race_time);
+    while low < high {
+        let middle = high - (high - low) / 2;
+        if beats(middle) {
+            low = middle;
+        } else {
+            high = middle - 1;
+        }
+    }
+    Some((min, low))
+}
 
 fn main() {
     let args: Vec<String> = std::env::args().collect();
     let file_name = args.get(1).unwrap();
 
-    let lines = fs::read_to_string(file_name).unwrap();
-    let mut lines = lines.lines();
+    let file = fs::read_to_string(file_name).unwrap();
+    let model = match args.iter().find_map(|arg| arg.strip_prefix("--model=")) {
+        Some(model) => parse_model(model).unwrap_or_else(|err| {
+            eprintln!("{err}");
+            process::exit(1);
+        }),
+        None => Box::new(LinearCharge),
+    };
 
-    let times = lines.next().unwrap().replace("Time:", "");
-    let times = times.trim();
-    let times = parse_row("Time: ", times);
+    // Joined rows can have more digits than fit in a u64. Only the linear model
+    // is supported for these, and the table and plot are not drawn, so flags
+    // asking for them are rejected rather than silently ignored.
+    if args.iter().any(|arg| arg == "--big") {
+        let unsupported = args
+            .iter()
+            .find(|arg| arg.starts_with("--model=") || *arg == "--table" || *arg == "--plot");
+        if let Some(flag) = unsupported {
+            eprintln!("{flag} cannot be combined with --big");
+            process::exit(1);
+        }
+        let races = |kerning| {
+            parse_races::<BigUint>(&file, kerning).unwrap_or_else(|err| {
+                eprintln!("Invalid races in {file_name}: {err}");
+                process::exit(1);
+            })
+        };
+        let result: BigUint = races(Kerning::Separate)
+            .iter()
+            .map(|(time, distance)| num_solutions_big(time, distance))
+            .product();
+        println!("Part 1: {result}");
 
-    let distances = lines.next().unwrap().replace("Distance:", "");
-    let distances = distances.trim();
-    let distances = parse_row("Distance:", distances);
+        let (time, distance) = &races(Kerning::Joined)[0];
+        let result = num_solutions_big(time, distance);
+        println!("Part 2: {result}");
+        return;
+    }
 
-    let result: u64 = times.iter().zip(distances)
-        .map(|(time, distance)| num_solutions(*time, distance))
-        .product();
+    let races = |kerning| {
+        parse_races::<u64>(&file, kerning).unwrap_or_else(|err| {
+            eprintln!(
+                "Invalid races in {file_name}: {err}; numbers too large for 64 bits need --big"
+            );
+            process::exit(1);
+        })
+    };
+    let result = races(Kerning::Separate)
+        .into_iter()
+        .map(|(time, distance)| model.num_solutions(time, distance))
+        .try_fold(1u64, u64::checked_mul)
+        .unwrap_or_else(|| {
+            eprintln!("Part 1 does not fit in 64 bits, rerun with --big");
+            process::exit(1);
+        });
     println!("Part 1: {result}");
 
-    // Got lazy and hard-coded instead of parsing
-    let result = num_solutions(61677571, 430103613071150);
+    let (time, distance) = races(Kerning::Joined)[0];
+    let result = model.num_solutions(time, distance);
     println!("Part 2: {result}");
+
+    let races = races(Kerning::Separate);
+    if args.iter().any(|arg| arg == "--table") {
+        let mut labelled: Vec<(String, u64, u64)> = races
+            .iter()
+            .enumerate()
+            .map(|(index, (time, record))| ((index + 1).to_string(), *time, *record))
+            .collect();
+        labelled.push(("joined".to_string(), time, distance));
+        println!();
+        print!("{}", format_table(model.as_ref(), &labelled));
+    }
+    if args.iter().any(|arg| arg == "--plot") {
+        for (index, (time, record)) in races.iter().enumerate() {
+            println!();
+            println!("Race {}: time {time}, record {record}", index + 1);
+            print!("{}", plot_race(model.as_ref(), *time, *record, 60, 16));
+        }
+    }
+}
+
+fn format_table(model: &dyn BoatModel, races: &[(String, u64, u64)]) -> String {
+    let mut output = format!(
+        "{:>8} {:>12} {:>16} {:>10} {:>10} {:>10} {:>16}\n",
+        "Race", "Time", "Record", "Min hold", "Max hold", "Count", "Best"
+    );
+    for (label, time, record) in races {
+        let (min, max) = match model.winning_holds(*time, *record) {
+            Some((min, max)) => (min.to_string(), max.to_string()),
+            None => ("-".to_string(), "-".to_string()),
+        };
+        let best = model.distance(*time, model.optimal_hold(*time));
+        output += &format!(
+            "{:>8} {:>12} {:>16} {:>10} {:>10} {:>10} {:>16}\n",
+            label,
+            time,
+            record,
+            min,
+            max,
+            model.num_solutions(*time, *record),
+            best
+        );
+    }
+    output
 }
 
-fn parse_row(header: &str, row: &str) -> Vec<u64> {
-    row.replace(header, "")
-        .trim()
-        .split_ascii_whitespace()
-        .map(|number| number.parse().unwrap())
-        .collect()
+// Plots distance against hold time with '*', the record as a line of '-', and
+// shades between the two with ':' wherever the hold time wins. Long races are
+// sampled down to `width` columns.
+fn plot_race(model: &dyn BoatModel, time: u64, record: u64, width: u64, height: u64) -> String {
+    let columns = width.min(time + 1).max(1);
+    let holds: Vec<u64> = (0..columns)
+        .map(|column| match columns {
+            1 => 0,
+            _ => (column as u128 * time as u128 / (columns - 1) as u128) as u64,
+        })
+        .collect();
+    let distances: Vec<u128> = holds
+        .iter()
+        .map(|hold| model.distance(time, *hold))
+        .collect();
+    let top = distances
+        .iter()
+        .copied()
+        .chain([record as u128, 1])
+        .max()
+        .unwrap();
+    let row_of = |distance: u128| (distance * (height - 1) as u128 + top / 2) / top;
+    let record_row = row_of(record as u128);
+
+    let mut output = String::new();
+    for row in (0..height as u128).rev() {
+        output.push('|');
+        for distance in distances.iter() {
+            let curve_row = row_of(*distance);
+            let cell = if row == curve_row {
+                '*'
+            } else if *distance > record as u128 && row >= record_row && row < curve_row {
+                ':'
+            } else if row == record_row {
+                '-'
+            } else {
+                ' '
+            };
+            output.push(cell);
+        }
+        output.push('\n');
+    }
+    output += &format!("+{}\n", "-".repeat(columns as usize));
+    output += &format!(
+        "0{}{time}\n",
+        " ".repeat((columns as usize).saturating_sub(time.to_string().len()))
+    );
+    output
+}
+
+fn parse_races<T: FromStr>(input: &str, kerning: Kerning) -> Result<Vec<(T, T)>, String> {
+    let mut lines = input.lines();
+    let times = parse_row("Time:", lines.next().unwrap(), kerning)?;
+    let distances = parse_row("Distance:", lines.next().unwrap(), kerning)?;
+    Ok(times.into_iter().zip(distances).collect())
+}
+
+fn parse_row<T: FromStr>(header: &str, row: &str, kerning: Kerning) -> Result<Vec<T>, String> {
+    let numbers = row.replace(header, "");
+    let numbers = numbers.split_ascii_whitespace();
+    let parse = |number: &str| {
+        number
+            .parse()
+            .map_err(|_| format!("{header} value '{number}' is not a valid number"))
+    };
+    match kerning {
+        Kerning::Separate => numbers.map(parse).collect(),
+        Kerning::Joined => Ok(vec![parse(&numbers.collect::<String>())?]),
+    }
 }
 
 fn num_solutions(race_time: u64, distance: u64) -> u64 {
@@ -40,53 +384,109 @@ fn num_solutions(race_time: u64, distance: u64) -> u64 {
     }
 }
 
+fn num_solutions_big(race_time: &BigUint, distance: &BigUint) -> BigUint {
+    match solve_race_big(race_time, distance) {
+        Some((min, max)) => max - min + 1u32,
+        None => BigUint::zero(),
+    }
+}
+
 // h := hold time
 // T := race time
 // d := distance
 // d = h * (T - h) = -h^2 + Th
 // => h^2 - Th + d = 0
+//
+// A hold time wins when h * (T - h) > d. That is symmetric about T / 2, so the
+// winners are min..=(T - min) for the smallest winner min. Rounding the smaller
+// root using the integer square root of the discriminant puts min within one
+// step, and checking the inequality exactly settles it.
 #[allow(non_snake_case)]
 fn solve_race(T: u64, d: u64) -> Option<(u64, u64)> {
-    let T = T as f64;
-    let d = d as f64;
-
-    let discriminant = (-T).powi(2) - 4f64 * d;
-    if discriminant < 0f64 {
+    let beats = |h: u64| (h as u128) * ((T - h) as u128) > d as u128;
+    if !beats(T / 2) {
         return None;
     }
 
-    let min_solution = (T - discriminant.sqrt()) / 2f64;
-    let max_solution = (T + discriminant.sqrt()) / 2f64;
+    // Non-negative, as T^2 / 4 >= (T / 2) * (T - T / 2) > d
+    let discriminant = (T as u128).pow(2) - 4 * d as u128;
+    let mut min = ((T as u128 - discriminant.isqrt()) / 2) as u64;
+    while min > 0 && beats(min - 1) {
+        min -= 1;
+    }
+    while !beats(min) {
+        min += 1;
+    }
 
-    let min_solution_int = min_solution.ceil();
-    let min_solution_int = if min_solution == min_solution_int {
-        min_solution_int + 1f64
-    } else {
-        min_solution_int
-    } as u64;
+    Some((min, T - min))
+}
 
-    let max_solution_int = max_solution.floor();
-    let max_solution_int = if max_solution == max_solution_int {
-        max_solution_int - 1f64
-    } else {
-        max_solution_int
-    } as u64;
+#[allow(non_snake_case)]
+fn solve_race_big(T: &BigUint, d: &BigUint) -> Option<(BigUint, BigUint)> {
+    let beats = |h: &BigUint| h * (T - h) > *d;
+    let half: BigUint = T / 2u32;
+    if !beats(&half) {
+        return None;
+    }
+
+    let discriminant: BigUint = T * T - d * 4u32;
+    let mut min: BigUint = (T - discriminant.sqrt()) / 2u32;
+    while !min.is_zero() && beats(&(&min - 1u32)) {
+        min -= 1u32;
+    }
+    while !beats(&min) {
+        min += BigUint::one();
+    }
 
-    Some((min_solution_int, max_solution_int))
+    let max = T - &min;
+    Some((min, max))
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use proptest::prelude::*;
 
     #[test]
     fn should_parse_row() {
         let row = "Time:      7  15   30";
 
-        let times = parse_row("Time:", row);
+        let times = parse_row("Time:", row, Kerning::Separate);
 
         let expected_times: Vec<u64> = vec![7, 15, 30];
-        assert_eq!(expected_times, times);
+        assert_eq!(Ok(expected_times), times);
+    }
+
+    #[test]
+    fn should_parse_row_with_joined_kerning() {
+        let row = "Time:      7  15   30";
+
+        let times = parse_row("Time:", row, Kerning::Joined);
+
+        assert_eq!(Ok(vec![71530]), times);
+    }
+
+    #[test]
+    fn should_reject_joined_row_too_long_for_u64() {
+        let row = "Time:      71530  71530   71530  71530";
+
+        let times = parse_row::<u64>("Time:", row, Kerning::Joined);
+        let separate = parse_row::<u64>("Time:", "Time: 7 x 30", Kerning::Separate);
+
+        let expected = "Time: value '71530715307153071530' is not a valid number";
+        assert_eq!(Err(expected.to_string()), times);
+        assert_eq!(
+            Err("Time: value 'x' is not a valid number".to_string()),
+            separate
+        );
+    }
+
+    #[test]
+    fn should_solve_example_part_two() {
+        let races = parse_races(include_str!("../resources/test.txt"), Kerning::Joined).unwrap();
+
+        assert_eq!(vec![(71530, 940200)], races);
+        assert_eq!(71503, num_solutions(71530, 940200));
     }
 
     #[test]
@@ -94,7 +494,6 @@ mod tests {
         let result = solve_race(7, 9);
 
         assert!(result.is_some());
-        let (min, max) = result.unwrap();
         assert_eq!((2, 5), result.unwrap());
     }
 
@@ -123,8 +522,210 @@ mod tests {
     #[test]
     fn should_not_double_count_degenerate_solution() {
         // Only solution is h = 3
-        let solutions = num_solutions(6, 9);
+        let solutions = num_solutions(6, 8);
 
         assert_eq!(solutions, 1);
     }
+
+    #[test]
+    fn should_not_count_hold_time_that_only_ties_record() {
+        // h = 3 travels exactly 9, which doesn't beat the record
+        let solutions = num_solutions(6, 9);
+
+        assert_eq!(solutions, 0);
+    }
+
+    #[test]
+    fn should_solve_race_with_no_time() {
+        assert_eq!(None, solve_race(0, 0));
+        assert_eq!(Some((1, 1)), solve_race(2, 0));
+    }
+
+    #[test]
+    fn should_solve_race_near_u64_max() {
+        let time = u64::MAX;
+
+        // h = 1 travels u64::MAX - 1 and h = 2 travels 2 * (u64::MAX - 2)
+        assert_eq!(Some((1, time - 1)), solve_race(time, time - 2));
+        assert_eq!(Some((2, time - 2)), solve_race(time, time - 1));
+        assert_eq!(Some((2, time - 2)), solve_race(time, time));
+    }
+
+    #[test]
+    fn should_solve_joined_race_too_long_for_u64() {
+        let row = "Time:      71530  71530   71530  71530";
+
+        let times: Vec<BigUint> = parse_row("Time:", row, Kerning::Joined).unwrap();
+        let record: BigUint = "940200940200940200940200".parse().unwrap();
+
+        let (min, max) = solve_race_big(&times[0], &record).unwrap();
+        let beats = |h: &BigUint| h * (&times[0] - h) > record;
+        assert!(beats(&min) && !beats(&(&min - 1u32)));
+        assert!(beats(&max) && !beats(&(&max + 1u32)));
+    }
+
+    fn brute_force(time: u64, distance: u64) -> u64 {
+        (0..=time)
+            .filter(|hold| hold * (time - hold) > distance)
+            .count() as u64
+    }
+
+    fn beats(time: u64, distance: u64, hold: u64) -> bool {
+        hold as u128 * (time - hold) as u128 > distance as u128
+    }
+
+    proptest! {
+        #[test]
+        fn should_match_brute_force_count(time in 0..300u64, distance in 0..23_000u64) {
+            prop_assert_eq!(brute_force(time, distance), num_solutions(time, distance));
+        }
+
+        #[test]
+        fn should_find_exact_bounds_near_u64_max(
+            time in (u64::MAX - 1_000_000)..=u64::MAX,
+            slack in 0..u64::MAX,
+        ) {
+            // Records up to u64::MAX are always beaten by a hold near T / 2, so
+            // check the bounds are tight rather than just present.
+            let (min, max) = solve_race(time, slack).unwrap();
+
+            prop_assert!(beats(time, slack, min));
+            prop_assert!(min == 0 || !beats(time, slack, min - 1));
+            prop_assert!(beats(time, slack, max));
+            prop_assert!(max == time || !beats(time, slack, max + 1));
+        }
+
+        #[test]
+        fn should_match_big_integer_solver(time in any::<u64>(), distance in any::<u64>()) {
+            let expected = solve_race(time, distance)
+                .map(|(min, max)| (BigUint::from(min), BigUint::from(max)));
+
+            prop_assert_eq!(
+                expected,
+                solve_race_big(&BigUint::from(time), &BigUint::from(distance))
+            );
+        }
+    }
+
+    fn brute_force_holds(model: &dyn BoatModel, time: u64, distance: u64) -> Option<(u64, u64)> {
+        let winners: Vec<u64> = (0..=time)
+            .filter(|hold| model.distance(time, *hold) > distance as u128)
+            .collect();
+        Some((*winners.first()?, *winners.last()?))
+    }
+
+    fn brute_force_best(model: &dyn BoatModel, time: u64) -> u128 {
+        (0..=time)
+            .map(|hold| model.distance(time, hold))
+            .max()
+            .unwrap()
+    }
+
+    fn models() -> impl Strategy<Value = Box<dyn BoatModel>> {
+        prop_oneof![
+            Just(()).prop_map(|_| Box::new(LinearCharge) as Box<dyn BoatModel>),
+            (0..5u64).prop_map(|rate| Box::new(Acceleration { rate }) as Box<dyn BoatModel>),
+            (0..400u64).prop_map(|speed| Box::new(StartingSpeed { speed }) as Box<dyn BoatModel>),
+            (0..400u64)
+                .prop_map(|max_speed| Box::new(SpeedCap { max_speed }) as Box<dyn BoatModel>),
+        ]
+    }
+
+    #[test]
+    fn should_parse_models() {
+        assert!(parse_model("linear").is_ok());
+        assert!(parse_model("accel:3").is_ok());
+        assert!(parse_model("start:10").is_ok());
+        assert!(parse_model("cap:5").is_ok());
+        assert!(parse_model("cap:x").is_err());
+        assert!(parse_model("warp").is_err());
+    }
+
+    #[test]
+    fn should_cap_speed() {
+        let model = SpeedCap { max_speed: 3 };
+
+        // Holding past 3ms wastes time without going any faster
+        assert_eq!(Some((2, 5)), model.winning_holds(10, 12));
+        assert_eq!(3, model.optimal_hold(10));
+    }
+
+    #[test]
+    fn should_add_starting_speed() {
+        let model = StartingSpeed { speed: 4 };
+
+        // (4 + h) * (7 - h) > 28 only for h = 1 and h = 2
+        assert_eq!(Some((1, 2)), model.winning_holds(7, 28));
+        assert_eq!(1, model.optimal_hold(7));
+        assert_eq!(0, StartingSpeed { speed: 20 }.optimal_hold(7));
+    }
+
+    proptest! {
+        #[test]
+        fn should_match_brute_force_for_each_model(
+            model in models(),
+            time in 0..300u64,
+            distance in 0..40_000u64,
+        ) {
+            let model = model.as_ref();
+            prop_assert_eq!(
+                brute_force_holds(model, time, distance),
+                model.winning_holds(time, distance)
+            );
+            prop_assert_eq!(
+                brute_force_best(model, time),
+                model.distance(time, model.optimal_hold(time))
+            );
+        }
+
+        #[test]
+        fn should_match_search_fallback_for_closed_forms(
+            model in models(),
+            time in any::<u64>(),
+            distance in any::<u64>(),
+        ) {
+            let model = model.as_ref();
+            prop_assert_eq!(
+                search_winning_holds(model, time, distance),
+                model.winning_holds(time, distance)
+            );
+            prop_assert_eq!(
+                model.distance(time, search_optimal_hold(model, time)),
+                model.distance(time, model.optimal_hold(time))
+            );
+        }
+    }
+
+    #[test]
+    fn should_format_race_table() {
+        let races = vec![("1".to_string(), 7, 9), ("2".to_string(), 5, 9)];
+
+        let table = format_table(&LinearCharge, &races);
+
+        let expected = [
+            "    Race         Time           Record   Min hold   Max hold      Count             Best",
+            "       1            7                9          2          5          4               12",
+            "       2            5                9          -          -          0                6",
+            "",
+        ];
+        assert_eq!(expected.join("\n"), table);
+    }
+
+    #[test]
+    fn should_plot_race() {
+        let plot = plot_race(&LinearCharge, 7, 9, 60, 5);
+
+        // Distances 0, 6, 10, 12, 12, 10, 6, 0 against a record of 9
+        let expected = [
+            "|   **   ",
+            "|--*::*--",
+            "| *    * ",
+            "|        ",
+            "|*      *",
+            "+--------",
+            "0       7",
+            "",
+        ];
+        assert_eq!(expected.join("\n"), plot);
+    }
 }
@@ -1,22 +1,327 @@
-use std::cmp::Ordering;
-use std::collections::HashMap;
-use std::fs;
+use std::cmp::{Ordering, Reverse};
+use std::time::Instant;
+use std::{fmt, fs, process};
 
 fn main() {
     let args: Vec<String> = std::env::args().collect();
+    if let Some(count) = args.iter().find_map(|arg| arg.strip_prefix("--bench=")) {
+        let count: usize = count.parse().unwrap_or_else(|_| {
+            eprintln!("--bench needs a number of hands, got '{count}'");
+            process::exit(1);
+        });
+        bench(count);
+        return;
+    }
     let file_name = args.get(1).unwrap();
 
     let file = fs::read_to_string(file_name).unwrap();
 
-    let mut hands: Vec<Hand> = file.lines().map(|line| Hand::parse(line)).collect();
+    if args.iter().any(|arg| arg == "--poker") {
+        let poker = poker_winnings(&file).unwrap_or_else(|err| exit(file_name, err));
+        println!("Poker: {poker}");
+        return;
+    }
+
+    let mut rulesets = vec![("Part 1", Ruleset::jacks()), ("Part 2", Ruleset::jokers())];
+    if let Some(ranks) = args.iter().find_map(|arg| arg.strip_prefix("--ranks=")) {
+        let wild = args
+            .iter()
+            .find_map(|arg| arg.strip_prefix("--wild="))
+            .unwrap_or("");
+        let tie_break = parse_flag(&args, "--tie-break=", TieBreak::parse, TieBreak::Positional);
+        let hand_types = parse_flag(
+            &args,
+            "--hand-types=",
+            HandTable::parse,
+            HandTable::BySignature,
+        );
+        let custom = Ruleset::new(ranks, wild, tie_break)
+            .unwrap_or_else(|err| exit(file_name, err))
+            .with_hand_types(hand_types);
+        rulesets.push(("Custom", custom));
+    }
+
+    let report = parse_flag(
+        &args,
+        "--report=",
+        |value| ReportFormat::parse(value).map(Some),
+        None,
+    )
+    .or_else(|| {
+        args.iter()
+            .any(|arg| arg == "--report")
+            .then_some(ReportFormat::Text)
+    });
+    let show_ties = args.iter().any(|arg| arg == "--ties");
+
+    let mut sections = vec![];
+    for (label, ruleset) in rulesets.iter() {
+        let mut hands = parse_hands(&file, ruleset).unwrap_or_else(|err| exit(file_name, err));
+        if report.is_none() {
+            println!("{label}: {}", winnings(hands, |hand| hand.bet));
+        } else {
+            hands.sort();
+            sections.push((*label, hands));
+        }
+    }
+    if let Some(format) = report {
+        print!("{}", format_report(&sections, format, show_ties));
+    }
+}
+
+// Times parsing, typing and sorting generated hands under each built-in
+// ruleset. Typing is timed again on its own over the parsed cards.
+fn bench(count: usize) {
+    for (label, ruleset) in [("jacks", Ruleset::jacks()), ("jokers", Ruleset::jokers())] {
+        let input = generate_hands(count, &ruleset, 7);
+
+        let start = Instant::now();
+        let mut hands = parse_hands(&input, &ruleset).unwrap();
+        let parsed = start.elapsed();
+
+        let start = Instant::now();
+        let keys: Vec<SortKey> = hands
+            .iter()
+            .map(|hand| {
+                let hand_type = ruleset.hand_types.classify(&hand.cards).unwrap();
+                Hand::sort_key(&hand_type, &hand.cards, ruleset.tie_break)
+            })
+            .collect();
+        let typed = start.elapsed();
+        // Only the timing matters, but the keys must not be optimised away.
+        std::hint::black_box(keys);
+
+        let start = Instant::now();
+        hands.sort();
+        let sorted = start.elapsed();
+
+        println!(
+            "{label}: parsed {count} hands in {parsed:.1?} (typing {typed:.1?}), sorted in {sorted:.1?}"
+        );
+    }
+}
+
+// Five card hands with bets, from a xorshift generator so runs repeat.
+fn generate_hands(count: usize, ruleset: &Ruleset, seed: u64) -> String {
+    let mut state = seed.max(1);
+    let mut next = |bound: usize| {
+        state ^= state << 13;
+        state ^= state >> 7;
+        state ^= state << 17;
+        (state % bound as u64) as usize
+    };
+    let mut input = String::with_capacity(count * 11);
+    for _ in 0..count {
+        for _ in 0..5 {
+            input.push(ruleset.ranks[next(ruleset.ranks.len())]);
+        }
+        input += &format!(" {}\n", next(1000) + 1);
+    }
+    input
+}
+
+fn parse_flag<T>(
+    args: &[String],
+    prefix: &str,
+    parse: fn(&str) -> Result<T, GameError>,
+    default: T,
+) -> T {
+    match args.iter().find_map(|arg| arg.strip_prefix(prefix)) {
+        Some(value) => parse(value).unwrap_or_else(|err| {
+            eprintln!("{err}");
+            process::exit(1);
+        }),
+        None => default,
+    }
+}
+
+fn exit(file_name: &str, err: GameError) -> ! {
+    eprintln!("Invalid hands in {file_name}: {err}");
+    process::exit(1);
+}
+
+fn parse_hands(input: &str, ruleset: &Ruleset) -> Result<Vec<Hand>, GameError> {
+    input
+        .lines()
+        .map(|line| Hand::parse(line, ruleset))
+        .collect()
+}
+
+fn poker_winnings(input: &str) -> Result<u64, GameError> {
+    let hands = input
+        .lines()
+        .map(PokerHand::parse)
+        .collect::<Result<Vec<PokerHand>, GameError>>()?;
+    Ok(winnings(hands, |hand| hand.bet))
+}
+
+fn winnings<T: Ord>(mut hands: Vec<T>, bet: fn(&T) -> u32) -> u64 {
     hands.sort();
 
-    let winnings: u32 = hands
+    hands
         .iter()
         .enumerate()
-        .map(|(rank, hand)| ((rank as u32) + 1) * hand.bet)
-        .sum();
-    println!("Winnings: {winnings}")
+        .map(|(rank, hand)| (rank as u64 + 1) * bet(hand) as u64)
+        .sum()
+}
+
+#[derive(Debug, PartialEq, Eq)]
+enum GameError {
+    DuplicateSymbol(char),
+    TooManySymbols(usize),
+    UnknownWild(char),
+    UnknownCard(char),
+    MissingBet(String),
+    InvalidBet(String),
+    UnknownTieBreak(String),
+    InvalidHandType(String),
+    DuplicateHandType(Vec<u32>),
+    UnrankedHandType(Vec<u32>),
+    InvalidPokerCard(String),
+    DuplicatePokerCard(String),
+    WrongHandSize(usize),
+    TooManyCards(usize),
+    TooManyHandTypes(usize),
+    UnknownReportFormat(String),
+}
+
+impl fmt::Display for GameError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GameError::DuplicateSymbol(symbol) => {
+                write!(
+                    f,
+                    "card symbol '{symbol}' appears more than once in the rank order"
+                )
+            }
+            GameError::TooManySymbols(count) => write!(
+                f,
+                "rank order has {count} symbols but at most {} are supported",
+                Ruleset::MAX_SYMBOLS
+            ),
+            GameError::UnknownWild(symbol) => {
+                write!(f, "wild symbol '{symbol}' is not in the rank order")
+            }
+            GameError::UnknownCard(symbol) => write!(f, "unknown card symbol '{symbol}'"),
+            GameError::MissingBet(row) => write!(f, "hand '{row}' has no bet"),
+            GameError::InvalidBet(bet) => write!(f, "bet '{bet}' is not a number"),
+            GameError::UnknownTieBreak(value) => write!(
+                f,
+                "unknown tie-break '{value}' (expected positional or high-card)"
+            ),
+            GameError::InvalidHandType(value) => {
+                write!(f, "hand type '{value}' is not a list of positive counts")
+            }
+            GameError::DuplicateHandType(signature) => {
+                write!(f, "hand type {signature:?} is ranked more than once")
+            }
+            GameError::UnrankedHandType(signature) => {
+                write!(f, "hand type {signature:?} is not in the hand type table")
+            }
+            GameError::InvalidPokerCard(token) => write!(f, "'{token}' is not a playing card"),
+            GameError::DuplicatePokerCard(token) => {
+                write!(f, "card '{token}' appears more than once in a hand")
+            }
+            GameError::WrongHandSize(size) => {
+                write!(f, "poker hands need five to seven cards, found {size}")
+            }
+            GameError::TooManyCards(count) => write!(
+                f,
+                "hand has {count} cards but at most {} are supported",
+                Hand::MAX_CARDS
+            ),
+            GameError::TooManyHandTypes(count) => write!(
+                f,
+                "hand type table has {count} entries but at most {} are supported",
+                HandTable::MAX_TYPES
+            ),
+            GameError::UnknownReportFormat(value) => {
+                write!(f, "unknown report format '{value}' (expected text or csv)")
+            }
+        }
+    }
+}
+
+// How two hands of the same type are separated: card by card in the order
+// dealt, or like poker with both hands sorted from their highest card down.
+#[derive(Eq, PartialEq, Debug, Copy, Clone)]
+enum TieBreak {
+    Positional,
+    HighCard,
+}
+
+impl TieBreak {
+    fn parse(value: &str) -> Result<TieBreak, GameError> {
+        match value {
+            "positional" => Ok(TieBreak::Positional),
+            "high-card" => Ok(TieBreak::HighCard),
+            _ => Err(GameError::UnknownTieBreak(value.to_string())),
+        }
+    }
+}
+
+// Part 1 reads J as a Jack between 10 and Queen, part 2 as a wild Joker that
+// ranks below every other card. Both are just rank orders (lowest first) with
+// a set of wild symbols, so variant decks can be described the same way.
+#[derive(Eq, PartialEq, Debug, Clone)]
+struct Ruleset {
+    ranks: Vec<char>,
+    wild: Vec<char>,
+    tie_break: TieBreak,
+    hand_types: HandTable,
+}
+
+impl Ruleset {
+    // Hands are typed with a count per symbol held on the stack, and each
+    // rank must fit a six bit sort key digit.
+    const MAX_SYMBOLS: usize = 64;
+
+    fn new(ranks: &str, wild: &str, tie_break: TieBreak) -> Result<Ruleset, GameError> {
+        let ranks: Vec<char> = ranks.chars().collect();
+        if ranks.len() > Ruleset::MAX_SYMBOLS {
+            return Err(GameError::TooManySymbols(ranks.len()));
+        }
+        for (i, symbol) in ranks.iter().enumerate() {
+            if ranks[..i].contains(symbol) {
+                return Err(GameError::DuplicateSymbol(*symbol));
+            }
+        }
+        let wild: Vec<char> = wild.chars().collect();
+        if let Some(symbol) = wild.iter().find(|symbol| !ranks.contains(symbol)) {
+            return Err(GameError::UnknownWild(*symbol));
+        }
+        Ok(Ruleset {
+            ranks,
+            wild,
+            tie_break,
+            hand_types: HandTable::BySignature,
+        })
+    }
+
+    fn with_hand_types(self, hand_types: HandTable) -> Ruleset {
+        Ruleset { hand_types, ..self }
+    }
+
+    fn jacks() -> Ruleset {
+        Ruleset::new("23456789TJQKA", "", TieBreak::Positional).unwrap()
+    }
+
+    fn jokers() -> Ruleset {
+        Ruleset::new("J23456789TQKA", "J", TieBreak::Positional).unwrap()
+    }
+
+    fn card(&self, symbol: char) -> Result<Card, GameError> {
+        let rank = self
+            .ranks
+            .iter()
+            .position(|candidate| *candidate == symbol)
+            .ok_or(GameError::UnknownCard(symbol))?;
+        Ok(Card {
+            rank,
+            symbol,
+            wild: self.wild.contains(&symbol),
+        })
+    }
 }
 
 #[derive(Eq, PartialEq, Debug, Clone)]
@@ -24,8 +329,13 @@ struct Hand {
     cards: Vec<Card>,
     hand_type: HandType,
     bet: u32,
+    tie_break: TieBreak,
+    key: SortKey,
 }
 
+// Six bit digits packed most significant first, 21 to a word.
+type SortKey = [u128; 2];
+
 impl PartialOrd<Self> for Hand {
     fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
         Some(self.cmp(other))
@@ -34,407 +344,1307 @@ impl PartialOrd<Self> for Hand {
 
 impl Ord for Hand {
     fn cmp(&self, other: &Self) -> Ordering {
-        if self.hand_type != other.hand_type {
-            return self.hand_type.cmp(&other.hand_type);
-        }
-        for (ours, theirs) in self.cards.iter().zip(other.cards.iter()) {
-            let comparison = ours.cmp(theirs);
-            if comparison != Ordering::Equal {
-                return comparison;
-            }
-        }
-        Ordering::Equal
+        self.key.cmp(&other.key)
     }
 }
 
 impl Hand {
-    fn parse(row: &str) -> Hand {
+    // Keeps the signature and cards within the 42 digits of a sort key.
+    const MAX_CARDS: usize = 20;
+
+    fn parse(row: &str, ruleset: &Ruleset) -> Result<Hand, GameError> {
         let mut parts = row.split_ascii_whitespace();
-        let cards = parts.next().unwrap();
-        let bet = parts.next().unwrap();
+        let cards = parts.next().unwrap_or_default();
+        let bet = parts
+            .next()
+            .ok_or_else(|| GameError::MissingBet(row.to_string()))?;
 
-        let cards: Vec<Card> = cards
+        let cards = cards
             .chars()
-            .map(|character| Card::parse(character))
-            .collect();
-        let hand_type = Hand::get_type(&cards);
-        let bet: u32 = bet.parse().unwrap();
-        Hand {
+            .map(|symbol| ruleset.card(symbol))
+            .collect::<Result<Vec<Card>, GameError>>()?;
+        if cards.len() > Hand::MAX_CARDS {
+            return Err(GameError::TooManyCards(cards.len()));
+        }
+        let hand_type = ruleset.hand_types.classify(&cards)?;
+        let key = Hand::sort_key(&hand_type, &cards, ruleset.tie_break);
+        let bet: u32 = bet
+            .parse()
+            .map_err(|_| GameError::InvalidBet(bet.to_string()))?;
+        Ok(Hand {
             cards,
             hand_type,
             bet,
+            tie_break: ruleset.tie_break,
+            key,
+        })
+    }
+
+    fn symbols(&self) -> String {
+        self.cards.iter().map(|card| card.symbol).collect()
+    }
+
+    // The signature the cards would have if none of them were wild.
+    fn natural_signature(&self) -> Vec<u32> {
+        let cards: Vec<Card> = self
+            .cards
+            .iter()
+            .map(|card| Card {
+                wild: false,
+                ..*card
+            })
+            .collect();
+        Hand::signature(&cards)
+    }
+
+    // The hand type, noting what it was before any wild cards were counted.
+    fn describe_type(&self) -> String {
+        let natural = signature_name(&self.natural_signature());
+        let hand_type = self.hand_type.to_string();
+        if natural == hand_type {
+            hand_type
+        } else {
+            format!("{hand_type} (from {natural})")
         }
     }
 
-    fn get_type(cards: &Vec<Card>) -> HandType {
-        let mut counts: HashMap<Card, u32> = HashMap::new();
-        for card in cards.iter() {
-            let count = counts.entry(*card).or_insert(0);
-            *count += 1
+    // The first card, counting from one in tie-break order, that differs
+    // between this hand and another of the same type.
+    fn tie_break_card(&self, other: &Hand) -> Option<usize> {
+        let (ours, theirs) = match self.tie_break {
+            TieBreak::Positional => (self.cards.clone(), other.cards.clone()),
+            TieBreak::HighCard => (self.high_cards(), other.high_cards()),
+        };
+        ours.iter()
+            .zip(theirs.iter())
+            .position(|(a, b)| a != b)
+            .map(|i| i + 1)
+    }
+
+    fn high_cards(&self) -> Vec<Card> {
+        let mut cards = self.cards.clone();
+        cards.sort_by(|a, b| b.cmp(a));
+        cards
+    }
+
+    // Everything Ord needs packed into integers, so sorting never follows a
+    // pointer: the type's table rank, its signature ended by a zero (which
+    // sorts below any count, as a shorter signature would), then the card
+    // ranks in tie-break order. Hands of the same type have the same number
+    // of cards, so the ranks never need a terminator of their own.
+    fn sort_key(hand_type: &HandType, cards: &[Card], tie_break: TieBreak) -> SortKey {
+        let mut ranks = [0; Hand::MAX_CARDS];
+        for (i, card) in cards.iter().enumerate() {
+            ranks[i] = card.rank;
+        }
+        let ranks = &mut ranks[..cards.len()];
+        if tie_break == TieBreak::HighCard {
+            ranks.sort_unstable_by(|a, b| b.cmp(a));
         }
 
-        if counts.contains_key(&Card::Joker) && *counts.get(&Card::Joker).unwrap() != 5 {
-            let (most_common_card, max_count) = counts
-                .iter()
-                .filter(|(card, _)| *card != &Card::Joker)
-                .max_by_key(|(_card, count)| *count)
-                .unwrap_or_else(|| {
-                    let first_non_joker = counts
-                        .keys()
-                        .filter(|card| *card != &Card::Joker)
-                        .next()
-                        .unwrap();
-                    (first_non_joker, &counts[first_non_joker])
-                });
-            counts.insert(*most_common_card, max_count + counts[&Card::Joker]);
-        }
-
-        Hand::get_type_from_counts(counts)
-    }
-
-    fn get_type_from_counts(counts: HashMap<Card, u32>) -> HandType {
-        if counts.values().any(|count| *count == 5) {
-            HandType::FiveOfaKind
-        } else if counts.values().any(|count| *count == 4) {
-            HandType::FourOfAKind
-        } else if counts.values().any(|count| *count == 3) {
-            if counts.values().any(|count| *count == 2) {
-                HandType::FullHouse
-            } else {
-                HandType::ThreeOfAKind
-            }
-        } else {
-            let num_pairs = counts.values().filter(|count| **count == 2).count();
-            if num_pairs == 2 {
-                HandType::TwoPair
-            } else if num_pairs == 1 {
-                HandType::OnePair
+        let mut key = [0; 2];
+        let digits = [hand_type.rank]
+            .into_iter()
+            .chain(hand_type.signature.iter().map(|count| *count as usize))
+            .chain([0])
+            .chain(ranks.iter().copied());
+        for (position, digit) in digits.enumerate() {
+            key[position / 21] |= (digit as u128) << (6 * (20 - position % 21));
+        }
+        key
+    }
+
+    // Wild cards join whichever other card is most common, which gives the
+    // strongest signature when signatures compare directly. A hand of only
+    // wild cards is all of a kind. Ranked tables may order signatures any
+    // way, so they choose among every split of the wild cards instead.
+    fn signature(cards: &[Card]) -> Vec<u32> {
+        let (mut signature, wild) = Hand::natural_counts(cards);
+        match signature.first_mut() {
+            Some(max_count) => *max_count += wild,
+            None if wild > 0 => signature.push(wild),
+            None => {}
+        }
+        signature
+    }
+
+    // The counts of the cards that are not wild, largest first, and the
+    // number of wild cards.
+    fn natural_counts(cards: &[Card]) -> (Vec<u32>, u32) {
+        let mut counts = [0; Ruleset::MAX_SYMBOLS];
+        let mut distinct = Vec::with_capacity(cards.len());
+        let mut wild = 0;
+        for card in cards.iter() {
+            if card.wild {
+                wild += 1;
             } else {
-                HandType::HighCard
+                if counts[card.rank] == 0 {
+                    distinct.push(card.rank);
+                }
+                counts[card.rank] += 1;
             }
         }
+
+        let mut signature: Vec<u32> = distinct.iter().map(|rank| counts[*rank]).collect();
+        signature.sort_unstable_by(|a, b| b.cmp(a));
+        (signature, wild)
     }
 }
 
+// Cards order by their position in the ruleset's rank order; the symbol only
+// distinguishes cards for counting.
 #[derive(Ord, PartialOrd, Eq, PartialEq, Debug, Copy, Clone, Hash)]
-enum Card {
-    Joker,
-    Number(u32),
-    Picture(Royal),
-    Ace,
+struct Card {
+    rank: usize,
+    symbol: char,
+    wild: bool,
 }
 
-impl Card {
-    const KING: Card = Card::Picture(Royal::King);
-    const QUEEN: Card = Card::Picture(Royal::Queen);
+// A hand type is the multiset of card counts, largest first: [3, 2] is a full
+// house and [2, 2, 1] two pair in a five card hand. Types compare by their
+// position in the table, which for the built-in table is always zero so that
+// signatures compare directly (more of a kind beats fewer, for any hand size).
+#[derive(Ord, PartialOrd, Eq, PartialEq, Debug, Clone)]
+struct HandType {
+    rank: usize,
+    signature: Vec<u32>,
+}
 
-    fn parse(character: char) -> Card {
-        match character {
-            'K' => Card::KING,
-            'Q' => Card::QUEEN,
-            'J' => Card::Joker,
-            'T' => Card::Number(10),
-            'A' => Card::Ace,
-            _ => Card::Number(character.to_digit(10).unwrap()),
+impl fmt::Display for HandType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", signature_name(&self.signature))
+    }
+}
+
+#[derive(Eq, PartialEq, Debug, Clone)]
+enum HandTable {
+    BySignature,
+    Ranked(Vec<Vec<u32>>),
+}
+
+impl HandTable {
+    // Table ranks are the first sort key digit.
+    const MAX_TYPES: usize = 64;
+
+    // Parses signatures from weakest to strongest, e.g. "1,1,1;2,1;3".
+    fn parse(value: &str) -> Result<HandTable, GameError> {
+        let mut signatures: Vec<Vec<u32>> = vec![];
+        for group in value.split(';') {
+            let mut signature = group
+                .split(',')
+                .map(|count| count.trim().parse::<u32>())
+                .collect::<Result<Vec<u32>, _>>()
+                .map_err(|_| GameError::InvalidHandType(group.to_string()))?;
+            if signature.contains(&0) {
+                return Err(GameError::InvalidHandType(group.to_string()));
+            }
+            signature.sort_by(|a, b| b.cmp(a));
+            if signatures.contains(&signature) {
+                return Err(GameError::DuplicateHandType(signature));
+            }
+            signatures.push(signature);
+        }
+        if signatures.len() > HandTable::MAX_TYPES {
+            return Err(GameError::TooManyHandTypes(signatures.len()));
+        }
+        Ok(HandTable::Ranked(signatures))
+    }
+
+    fn classify(&self, cards: &[Card]) -> Result<HandType, GameError> {
+        let (natural, wild) = Hand::natural_counts(cards);
+        let signatures = match self {
+            HandTable::Ranked(signatures) if wild > 0 => signatures,
+            _ => return self.hand_type(Hand::signature(cards)),
+        };
+        signatures
+            .iter()
+            .rposition(|candidate| HandTable::reachable(&natural, wild, candidate))
+            .map(|rank| HandType {
+                rank,
+                signature: signatures[rank].clone(),
+            })
+            .ok_or_else(|| GameError::UnrankedHandType(Hand::signature(cards)))
+    }
+
+    // Whether wild cards can turn the natural counts into the target. Each
+    // natural group needs a target group at least its size, which pairing
+    // both lists largest first finds if any pairing does; the wild cards
+    // top those groups up and make up any target groups left over.
+    fn reachable(natural: &[u32], wild: u32, target: &[u32]) -> bool {
+        natural.len() <= target.len()
+            && natural
+                .iter()
+                .zip(target.iter())
+                .all(|(count, goal)| count <= goal)
+            && natural.iter().sum::<u32>() + wild == target.iter().sum::<u32>()
+    }
+
+    fn hand_type(&self, signature: Vec<u32>) -> Result<HandType, GameError> {
+        let rank = match self {
+            HandTable::BySignature => 0,
+            HandTable::Ranked(signatures) => signatures
+                .iter()
+                .position(|candidate| *candidate == signature)
+                .ok_or_else(|| GameError::UnrankedHandType(signature.clone()))?,
+        };
+        Ok(HandType { rank, signature })
+    }
+}
+
+#[derive(Eq, PartialEq, Debug, Copy, Clone)]
+enum ReportFormat {
+    Text,
+    Csv,
+}
+
+impl ReportFormat {
+    fn parse(value: &str) -> Result<ReportFormat, GameError> {
+        match value {
+            "text" => Ok(ReportFormat::Text),
+            "csv" => Ok(ReportFormat::Csv),
+            _ => Err(GameError::UnknownReportFormat(value.to_string())),
         }
     }
 }
 
+// One section per ruleset, each with its hands already sorted weakest first.
+// Upgraded hands show the type they would have had without wild cards, and
+// with show_ties each hand of the same type as the one ranked just below it
+// names the card that separated them.
+fn format_report(sections: &[(&str, Vec<Hand>)], format: ReportFormat, show_ties: bool) -> String {
+    let mut report = String::new();
+    if format == ReportFormat::Csv {
+        report += "part,rank,cards,type,natural_type,bet,winnings";
+        report += if show_ties { ",tie_break\n" } else { "\n" };
+    }
+    for (label, hands) in sections.iter() {
+        let width = hands
+            .iter()
+            .map(|hand| hand.cards.len())
+            .max()
+            .unwrap_or(0)
+            .max(5);
+        let type_width = hands
+            .iter()
+            .map(|hand| hand.describe_type().len())
+            .max()
+            .unwrap_or(0)
+            .max(4);
+        if format == ReportFormat::Text {
+            let total = hands
+                .iter()
+                .enumerate()
+                .map(|(i, hand)| (i as u64 + 1) * hand.bet as u64)
+                .sum::<u64>();
+            report += &format!("{label} (winnings {total})\n");
+            report += &format!(
+                "{:>4}  {:<width$}  {:<type_width$}  {:>6}  {:>8}",
+                "Rank", "Cards", "Type", "Bet", "Winnings"
+            );
+            report += if show_ties { "  Tie-break\n" } else { "\n" };
+        }
+        for (i, hand) in hands.iter().enumerate() {
+            let rank = i + 1;
+            let cards = hand.symbols();
+            let natural = signature_name(&hand.natural_signature());
+            let winnings = rank as u64 * hand.bet as u64;
+            let tie = match i.checked_sub(1).map(|below| &hands[below]) {
+                Some(below) if below.hand_type == hand.hand_type => {
+                    match hand.tie_break_card(below) {
+                        Some(card) => format!("card {card} over #{i}"),
+                        None => format!("equal to #{i}"),
+                    }
+                }
+                _ => String::new(),
+            };
+            match format {
+                ReportFormat::Text => {
+                    let row = format!(
+                        "{rank:>4}  {cards:<width$}  {:<type_width$}  {:>6}  {winnings:>8}",
+                        hand.describe_type(),
+                        hand.bet
+                    );
+                    if show_ties {
+                        report += format!("{row}  {tie}").trim_end();
+                    } else {
+                        report += &row;
+                    }
+                    report += "\n";
+                }
+                ReportFormat::Csv => {
+                    report += &format!(
+                        "{},{rank},{},{},{natural},{},{winnings}",
+                        csv_field(label),
+                        csv_field(&cards),
+                        hand.hand_type,
+                        hand.bet
+                    );
+                    if show_ties {
+                        report += &format!(",{tie}");
+                    }
+                    report += "\n";
+                }
+            }
+        }
+    }
+    report
+}
+
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+// Five card names where they exist, otherwise the signature itself.
+fn signature_name(signature: &[u32]) -> String {
+    const OF_A_KIND: [&str; 7] = ["four", "five", "six", "seven", "eight", "nine", "ten"];
+    let groups: Vec<u32> = signature
+        .iter()
+        .copied()
+        .filter(|count| *count > 1)
+        .collect();
+    match groups.as_slice() {
+        [] => "high card".to_string(),
+        [2] => "one pair".to_string(),
+        [2, 2] => "two pair".to_string(),
+        [3] => "three of a kind".to_string(),
+        [3, 2] => "full house".to_string(),
+        [count] if (4..=10).contains(count) => {
+            format!("{} of a kind", OF_A_KIND[*count as usize - 4])
+        }
+        _ => signature
+            .iter()
+            .map(|count| count.to_string())
+            .collect::<Vec<String>>()
+            .join("-"),
+    }
+}
+
 #[derive(Ord, PartialOrd, Eq, PartialEq, Debug, Copy, Clone, Hash)]
-enum Royal {
-    Queen,
-    King,
+enum Suit {
+    Clubs,
+    Diamonds,
+    Hearts,
+    Spades,
+}
+
+impl Suit {
+    fn parse(symbol: char) -> Option<Suit> {
+        match symbol {
+            'C' => Some(Suit::Clubs),
+            'D' => Some(Suit::Diamonds),
+            'H' => Some(Suit::Hearts),
+            'S' => Some(Suit::Spades),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Eq, PartialEq, Debug, Copy, Clone)]
+struct PokerCard {
+    card: Card,
+    suit: Suit,
+}
+
+impl PokerCard {
+    // A rank followed by a suit letter, e.g. "AS", "10H" or "TD".
+    fn parse(token: &str, ruleset: &Ruleset) -> Result<PokerCard, GameError> {
+        let invalid = || GameError::InvalidPokerCard(token.to_string());
+        let mut chars = token.chars();
+        let suit = chars
+            .next_back()
+            .and_then(Suit::parse)
+            .ok_or_else(invalid)?;
+        let mut rank = chars.as_str().chars();
+        let symbol = match (rank.next(), rank.next(), rank.next()) {
+            (Some('1'), Some('0'), None) => 'T',
+            (Some(symbol), None, None) => symbol,
+            _ => return Err(invalid()),
+        };
+        let card = ruleset.card(symbol).map_err(|_| invalid())?;
+        Ok(PokerCard { card, suit })
+    }
 }
 
-#[derive(Ord, PartialOrd, Eq, PartialEq, Debug, Copy, Clone)]
-enum HandType {
+#[derive(Ord, PartialOrd, Eq, PartialEq, Debug, Copy, Clone, Hash)]
+enum PokerCategory {
     HighCard,
     OnePair,
     TwoPair,
     ThreeOfAKind,
+    Straight,
+    Flush,
     FullHouse,
     FourOfAKind,
-    FiveOfaKind,
+    StraightFlush,
+}
+
+// The category, then the card ranks that separate hands within it from most
+// to least significant: the grouped ranks followed by the kickers, or just the
+// top card of a straight.
+#[derive(Ord, PartialOrd, Eq, PartialEq, Debug, Clone, Hash)]
+struct PokerRank {
+    category: PokerCategory,
+    tie_break: Vec<usize>,
+}
+
+impl PokerRank {
+    const ACE: usize = 12;
+    const FIVE: usize = 3;
+
+    fn evaluate(cards: &[PokerCard]) -> PokerRank {
+        let mut counts = [0; PokerRank::ACE + 1];
+        for card in cards.iter() {
+            counts[card.card.rank] += 1;
+        }
+        // Highest rank first, then stably move the larger groups to the front.
+        let mut groups: Vec<(u32, usize)> = (0..counts.len())
+            .rev()
+            .filter(|rank| counts[*rank] > 0)
+            .map(|rank| (counts[rank], rank))
+            .collect();
+        groups.sort_by_key(|(count, _)| Reverse(*count));
+
+        let flush = cards.iter().all(|card| card.suit == cards[0].suit);
+        let straight = match groups.as_slice() {
+            [(1, high), .., (1, low)] if groups.len() == 5 && high - low == 4 => Some(*high),
+            [(1, PokerRank::ACE), (1, PokerRank::FIVE), ..] if groups.len() == 5 => {
+                Some(PokerRank::FIVE)
+            }
+            _ => None,
+        };
+
+        let category = match (straight, flush, groups[0].0, groups.get(1).map(|g| g.0)) {
+            (Some(_), true, _, _) => PokerCategory::StraightFlush,
+            (_, _, 4, _) => PokerCategory::FourOfAKind,
+            (_, _, 3, Some(2)) => PokerCategory::FullHouse,
+            (_, true, _, _) => PokerCategory::Flush,
+            (Some(_), _, _, _) => PokerCategory::Straight,
+            (_, _, 3, _) => PokerCategory::ThreeOfAKind,
+            (_, _, 2, Some(2)) => PokerCategory::TwoPair,
+            (_, _, 2, _) => PokerCategory::OnePair,
+            _ => PokerCategory::HighCard,
+        };
+        let tie_break = match straight {
+            Some(high) => vec![high],
+            None => groups.iter().map(|(_, rank)| *rank).collect(),
+        };
+        PokerRank {
+            category,
+            tie_break,
+        }
+    }
+
+    // The strongest five card hand that can be made from five to seven cards.
+    fn best(cards: &[PokerCard]) -> PokerRank {
+        (0u32..1 << cards.len())
+            .filter(|mask| mask.count_ones() == 5)
+            .map(|mask| {
+                let chosen: Vec<PokerCard> = (0..cards.len())
+                    .filter(|i| mask & (1 << i) != 0)
+                    .map(|i| cards[i])
+                    .collect();
+                PokerRank::evaluate(&chosen)
+            })
+            .max()
+            .unwrap()
+    }
+}
+
+#[derive(Eq, PartialEq, Debug, Clone)]
+struct PokerHand {
+    cards: Vec<PokerCard>,
+    rank: PokerRank,
+    bet: u32,
+}
+
+impl PartialOrd<Self> for PokerHand {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for PokerHand {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.rank.cmp(&other.rank)
+    }
+}
+
+impl PokerHand {
+    // Between five and seven cards separated by spaces, then the bet.
+    fn parse(row: &str) -> Result<PokerHand, GameError> {
+        let ruleset = Ruleset::jacks();
+        let mut tokens: Vec<&str> = row.split_ascii_whitespace().collect();
+        let bet = tokens
+            .pop()
+            .ok_or_else(|| GameError::MissingBet(row.to_string()))?;
+
+        let cards = tokens
+            .iter()
+            .map(|token| PokerCard::parse(token, &ruleset))
+            .collect::<Result<Vec<PokerCard>, GameError>>()?;
+        if !(5..=7).contains(&cards.len()) {
+            return Err(GameError::WrongHandSize(cards.len()));
+        }
+        for (i, card) in cards.iter().enumerate() {
+            if cards[..i].contains(card) {
+                return Err(GameError::DuplicatePokerCard(tokens[i].to_string()));
+            }
+        }
+        let rank = PokerRank::best(&cards);
+        let bet: u32 = bet
+            .parse()
+            .map_err(|_| GameError::InvalidBet(bet.to_string()))?;
+        Ok(PokerHand { cards, rank, bet })
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::Card::Number;
 
-    #[test]
-    fn should_order_number_cards() {
-        let mut cards = vec![Number(3), Number(8), Number(5)];
+    const FIVE_OF_A_KIND: &[u32] = &[5];
+    const FOUR_OF_A_KIND: &[u32] = &[4, 1];
+    const FULL_HOUSE: &[u32] = &[3, 2];
+    const THREE_OF_A_KIND: &[u32] = &[3, 1, 1];
+    const TWO_PAIR: &[u32] = &[2, 2, 1];
+    const ONE_PAIR: &[u32] = &[2, 1, 1, 1];
+    const HIGH_CARD: &[u32] = &[1, 1, 1, 1, 1];
 
-        cards.sort();
+    fn cards(symbols: &str, ruleset: &Ruleset) -> Vec<Card> {
+        symbols
+            .chars()
+            .map(|symbol| ruleset.card(symbol).unwrap())
+            .collect()
+    }
 
-        let expected = vec![Number(3), Number(5), Number(8)];
-        assert_eq!(expected, cards);
+    fn hand(row: &str, ruleset: &Ruleset) -> Hand {
+        Hand::parse(row, ruleset).unwrap()
     }
 
     #[test]
-    fn should_order_number_and_picture_cards() {
-        let mut cards = vec![Number(3), Card::KING, Number(8), Card::QUEEN, Number(5)];
-
-        cards.sort();
+    fn should_reject_duplicate_symbols() {
+        let result = Ruleset::new("23452", "", TieBreak::Positional);
 
-        let expected = vec![Number(3), Number(5), Number(8), Card::QUEEN, Card::KING];
-        assert_eq!(expected, cards);
+        assert_eq!(Some(GameError::DuplicateSymbol('2')), result.err());
     }
 
     #[test]
-    fn should_order_hands_by_type_then_cards() {
-        let one = Hand {
-            cards: vec![Number(3), Number(2), Number(10), Number(3), Card::KING],
-            hand_type: HandType::OnePair,
-            bet: 0,
-        };
-        let two = Hand {
-            cards: vec![Card::KING, Number(10), Card::QUEEN, Card::QUEEN, Number(10)],
-            hand_type: HandType::TwoPair,
-            bet: 0,
-        };
-        let three = Hand {
-            cards: vec![Card::KING, Card::KING, Number(6), Number(7), Number(7)],
-            hand_type: HandType::TwoPair,
-            bet: 0,
-        };
-        let mut cards = vec![one.clone(), three.clone(), two.clone()];
+    fn should_reject_wild_symbols_outside_rank_order() {
+        let result = Ruleset::new("2345", "J", TieBreak::Positional);
 
-        cards.sort();
-
-        let expected = vec![one, two, three];
-        assert_eq!(expected, cards);
+        assert_eq!(Some(GameError::UnknownWild('J')), result.err());
     }
 
     #[test]
-    fn should_order_hands_by_type_then_cards_with_jokers() {
-        let one = Hand {
-            cards: vec![Number(3), Number(2), Number(10), Number(3), Card::KING],
-            hand_type: HandType::OnePair,
-            bet: 0,
-        };
-        let two = Hand {
-            cards: vec![Card::KING, Card::Joker, Number(10), Card::Joker, Number(10)],
-            hand_type: HandType::FourOfAKind,
-            bet: 0,
-        };
-        let three = Hand {
-            cards: vec![Card::KING, Card::Joker, Card::KING, Card::Joker, Number(10)],
-            hand_type: HandType::FourOfAKind,
-            bet: 0,
-        };
-        let four = Hand {
-            cards: vec![Card::KING, Number(2), Number(2), Number(2), Number(2)],
-            hand_type: HandType::FourOfAKind,
-            bet: 0,
-        };
-        let mut cards = vec![three.clone(), one.clone(), four.clone(), two.clone()];
-
-        cards.sort();
+    fn should_reject_unknown_card() {
+        let result = Hand::parse("T55X5 684", &Ruleset::jacks());
 
-        let expected = vec![one, two, three, four];
-        assert_eq!(expected, cards);
+        assert_eq!(Some(GameError::UnknownCard('X')), result.err());
     }
 
     #[test]
-    fn should_calculate_five_of_a_kind() {
-        let cards = vec![Number(3), Number(3), Number(3), Number(3), Number(3)];
+    fn should_score_variant_deck_with_two_wildcards() {
+        let ruleset = Ruleset::new("XY23456789TQKA", "XY", TieBreak::Positional).unwrap();
+
+        assert_eq!(
+            FIVE_OF_A_KIND,
+            hand("XY555 1", &ruleset).hand_type.signature
+        );
+        assert_eq!(FULL_HOUSE, hand("X2233 1", &ruleset).hand_type.signature);
+        assert_eq!(
+            FIVE_OF_A_KIND,
+            hand("XYXYX 1", &ruleset).hand_type.signature
+        );
+        assert!(hand("X5555 1", &ruleset) < hand("Y5555 1", &ruleset));
+    }
 
-        let hand_type = Hand::get_type(&cards);
+    #[test]
+    fn should_break_ties_by_high_card() {
+        let ruleset = Ruleset::new("23456789TJQKA", "", TieBreak::HighCard).unwrap();
+        let one = hand("KQ332 1", &ruleset);
+        let two = hand("23A34 1", &ruleset);
 
-        assert_eq!(HandType::FiveOfaKind, hand_type);
+        assert!(one < two);
+        assert!(hand("KQ332 1", &Ruleset::jacks()) > hand("23A34 1", &Ruleset::jacks()));
     }
 
     #[test]
-    fn should_calculate_four_of_a_kind() {
-        let cards = vec![Number(3), Number(2), Number(3), Number(3), Number(3)];
-
-        let hand_type = Hand::get_type(&cards);
+    fn should_order_three_card_hands() {
+        let ruleset = Ruleset::jacks();
+        let queen_high = hand("QKA 1", &ruleset);
+        let ace_high = hand("A23 1", &ruleset);
+        let low_pair = hand("2A2 1", &ruleset);
+        let high_pair = hand("A22 1", &ruleset);
+        let low_triple = hand("222 1", &ruleset);
+        let high_triple = hand("KKK 1", &ruleset);
+        let mut hands = vec![
+            high_triple.clone(),
+            ace_high.clone(),
+            low_pair.clone(),
+            low_triple.clone(),
+            queen_high.clone(),
+            high_pair.clone(),
+        ];
+
+        hands.sort();
+
+        let expected = vec![
+            queen_high,
+            ace_high,
+            low_pair,
+            high_pair,
+            low_triple,
+            high_triple,
+        ];
+        assert_eq!(expected, hands);
+        assert_eq!(vec![2, 1], hands[2].hand_type.signature);
+    }
 
-        assert_eq!(HandType::FourOfAKind, hand_type);
+    #[test]
+    fn should_order_seven_card_hands() {
+        let ruleset = Ruleset::jokers();
+        let high_card = hand("2345678 1", &ruleset);
+        let two_pair_and_more = hand("2233456 1", &ruleset);
+        let three_pair = hand("2233445 1", &ruleset);
+        let full_house = hand("2223345 1", &ruleset);
+        let two_triples = hand("222333A 1", &ruleset);
+        let four_of_a_kind = hand("J222345 1", &ruleset);
+        let seven_of_a_kind = hand("JJJJJJ2 1", &ruleset);
+        let mut hands = vec![
+            seven_of_a_kind.clone(),
+            two_triples.clone(),
+            high_card.clone(),
+            four_of_a_kind.clone(),
+            three_pair.clone(),
+            full_house.clone(),
+            two_pair_and_more.clone(),
+        ];
+
+        hands.sort();
+
+        let expected = vec![
+            high_card,
+            two_pair_and_more,
+            three_pair,
+            full_house,
+            two_triples,
+            four_of_a_kind,
+            seven_of_a_kind,
+        ];
+        assert_eq!(expected, hands);
+        assert_eq!(vec![7], hands[6].hand_type.signature);
     }
 
     #[test]
-    fn should_calculate_full_house() {
-        let cards = vec![Number(3), Card::KING, Number(3), Number(3), Card::KING];
+    fn should_rank_hand_types_from_table() {
+        let table = HandTable::parse("1,1,1;3;1,2").unwrap();
+        let ruleset = Ruleset::jacks().with_hand_types(table);
 
-        let hand_type = Hand::get_type(&cards);
+        let triple = hand("222 1", &ruleset);
+        let pair = hand("A2A 1", &ruleset);
 
-        assert_eq!(HandType::FullHouse, hand_type);
+        assert_eq!(1, triple.hand_type.rank);
+        assert_eq!(2, pair.hand_type.rank);
+        assert!(triple < pair);
     }
 
     #[test]
-    fn should_calculate_three_of_a_kind() {
-        let cards = vec![Number(3), Card::KING, Number(3), Number(3), Card::QUEEN];
+    fn should_reject_hand_type_missing_from_table() {
+        let table = HandTable::parse("1,1,1;2,1").unwrap();
+        let ruleset = Ruleset::jacks().with_hand_types(table);
 
-        let hand_type = Hand::get_type(&cards);
+        let result = Hand::parse("222 1", &ruleset);
 
-        assert_eq!(HandType::ThreeOfAKind, hand_type);
+        assert_eq!(Some(GameError::UnrankedHandType(vec![3])), result.err());
     }
 
     #[test]
-    fn should_calculate_two_pair() {
-        let cards = vec![Number(3), Card::KING, Card::QUEEN, Number(3), Card::QUEEN];
+    fn should_split_wild_cards_for_ranked_table() {
+        // Two pair outranks three of a kind here, so the joker is better
+        // spent pairing a single card than joining the kings.
+        let table = HandTable::parse("1,1,1,1,1;2,1,1,1;3,1,1;2,2,1;3,2").unwrap();
+        let ruleset = Ruleset::jokers().with_hand_types(table.clone());
+
+        let joker_pair = hand("KKJ23 1", &ruleset);
+        let full_house = hand("KKJ22 1", &ruleset);
+
+        assert_eq!(vec![2, 2, 1], joker_pair.hand_type.signature);
+        assert_eq!(3, joker_pair.hand_type.rank);
+        assert_eq!(vec![3, 2], full_house.hand_type.signature);
+        assert_eq!(
+            Some(GameError::UnrankedHandType(vec![5])),
+            Hand::parse("KKKKJ 1", &ruleset).err()
+        );
+        assert_eq!(
+            Ok(vec![2, 2, 1]),
+            table
+                .classify(&cards("JJ234", &Ruleset::jokers()))
+                .map(|hand_type| hand_type.signature)
+        );
+    }
 
-        let hand_type = Hand::get_type(&cards);
+    #[test]
+    fn should_reject_duplicate_hand_types() {
+        let result = HandTable::parse("1,1,1;2,1;1,2");
 
-        assert_eq!(HandType::TwoPair, hand_type);
+        assert_eq!(Some(GameError::DuplicateHandType(vec![2, 1])), result.err());
     }
 
     #[test]
-    fn should_calculate_one_pair() {
-        let cards = vec![Number(3), Card::KING, Card::QUEEN, Number(3), Number(8)];
-
-        let hand_type = Hand::get_type(&cards);
+    fn should_name_hand_types() {
+        assert_eq!("full house", signature_name(FULL_HOUSE));
+        assert_eq!("two pair", signature_name(&[2, 2, 1, 1, 1]));
+        assert_eq!("seven of a kind", signature_name(&[7]));
+        assert_eq!("3-3-1", signature_name(&[3, 3, 1]));
+    }
 
-        assert_eq!(HandType::OnePair, hand_type);
+    #[test]
+    fn should_format_text_report() {
+        let mut hands = parse_hands("JJ234 10\nKK677 28\nKK2JJ 3", &Ruleset::jokers()).unwrap();
+        hands.sort();
+
+        let report = format_report(&[("Part 2", hands)], ReportFormat::Text, true);
+
+        let expected = [
+            "Part 2 (winnings 57)",
+            "Rank  Cards  Type                                Bet  Winnings  Tie-break",
+            "   1  KK677  two pair                             28        28",
+            "   2  JJ234  three of a kind (from one pair)      10        20",
+            "   3  KK2JJ  four of a kind (from two pair)        3         9",
+        ];
+        assert_eq!(expected.join("\n") + "\n", report);
     }
 
     #[test]
-    fn should_calculate_high_card() {
-        let cards = vec![Number(3), Card::KING, Card::QUEEN, Number(9), Number(8)];
+    fn should_format_csv_report_with_ties() {
+        let mut hands = parse_hands("KK677 28\nKTJJT 220", &Ruleset::jacks()).unwrap();
+        hands.sort();
+
+        let report = format_report(&[("Part 1", hands)], ReportFormat::Csv, true);
+
+        let expected = [
+            "part,rank,cards,type,natural_type,bet,winnings,tie_break",
+            "Part 1,1,KTJJT,two pair,two pair,220,220,",
+            "Part 1,2,KK677,two pair,two pair,28,56,card 2 over #1",
+        ];
+        assert_eq!(expected.join("\n") + "\n", report);
+    }
 
-        let hand_type = Hand::get_type(&cards);
+    mod jacks {
+        use super::*;
 
-        assert_eq!(HandType::HighCard, hand_type);
-    }
+        #[test]
+        fn should_order_number_cards() {
+            let ruleset = Ruleset::jacks();
+            let mut cards = cards("385", &ruleset);
 
-    #[test]
-    fn should_parse_hand() {
-        let row = "T55Q5 684";
+            cards.sort();
 
-        let hand = Hand::parse(row);
+            assert_eq!(super::cards("358", &ruleset), cards);
+        }
 
-        let expected_hand = Hand {
-            cards: vec![Number(10), Number(5), Number(5), Card::QUEEN, Number(5)],
-            hand_type: HandType::ThreeOfAKind,
-            bet: 684,
-        };
-        assert_eq!(expected_hand, hand);
-    }
+        #[test]
+        fn should_order_number_and_picture_cards() {
+            let ruleset = Ruleset::jacks();
+            let mut cards = cards("3K8Q5", &ruleset);
 
-    #[test]
-    fn should_parse_hand_with_joker_four_of_a_kind() {
-        let row = "T55J5 684";
+            cards.sort();
 
-        let hand = Hand::parse(row);
+            assert_eq!(super::cards("358QK", &ruleset), cards);
+        }
 
-        let expected_hand = Hand {
-            cards: vec![Number(10), Number(5), Number(5), Card::Joker, Number(5)],
-            hand_type: HandType::FourOfAKind,
-            bet: 684,
-        };
-        assert_eq!(expected_hand, hand);
-    }
+        #[test]
+        fn should_order_hands_by_type_then_cards() {
+            let ruleset = Ruleset::jacks();
+            let one = hand("32T3K 0", &ruleset);
+            let two = hand("KTQQT 0", &ruleset);
+            let three = hand("KK677 0", &ruleset);
+            let mut hands = vec![one.clone(), three.clone(), two.clone()];
 
-    #[test]
-    fn should_parse_hand_with_joker_one_pair() {
-        let row = "T58J2 684";
+            hands.sort();
 
-        let hand = Hand::parse(row);
+            let expected = vec![one, two, three];
+            assert_eq!(expected, hands);
+        }
 
-        let expected_hand = Hand {
-            cards: vec![Number(10), Number(5), Number(8), Card::Joker, Number(2)],
-            hand_type: HandType::OnePair,
-            bet: 684,
-        };
-        assert_eq!(expected_hand, hand);
-    }
+        #[test]
+        fn should_calculate_five_of_a_kind() {
+            let cards = cards("33333", &Ruleset::jacks());
 
-    #[test]
-    fn should_parse_hand_with_joker_three_of_a_kind() {
-        let row = "TT8J2 684";
+            assert_eq!(FIVE_OF_A_KIND, Hand::signature(&cards));
+        }
 
-        let hand = Hand::parse(row);
+        #[test]
+        fn should_calculate_four_of_a_kind() {
+            let cards = cards("32333", &Ruleset::jacks());
 
-        let expected_hand = Hand {
-            cards: vec![Number(10), Number(10), Number(8), Card::Joker, Number(2)],
-            hand_type: HandType::ThreeOfAKind,
-            bet: 684,
-        };
-        assert_eq!(expected_hand, hand);
-    }
+            assert_eq!(FOUR_OF_A_KIND, Hand::signature(&cards));
+        }
 
-    #[test]
-    fn should_parse_hand_with_joker_five_of_a_kind() {
-        let row = "TTTJT 684";
+        #[test]
+        fn should_calculate_full_house() {
+            let cards = cards("3K33K", &Ruleset::jacks());
 
-        let hand = Hand::parse(row);
+            assert_eq!(FULL_HOUSE, Hand::signature(&cards));
+        }
 
-        let expected_hand = Hand {
-            cards: vec![Number(10), Number(10), Number(10), Card::Joker, Number(10)],
-            hand_type: HandType::FiveOfaKind,
-            bet: 684,
-        };
-        assert_eq!(expected_hand, hand);
-    }
+        #[test]
+        fn should_calculate_three_of_a_kind() {
+            let cards = cards("3K33Q", &Ruleset::jacks());
 
-    #[test]
-    fn should_parse_hand_with_joker_full_house() {
-        let row = "88J55 684";
+            assert_eq!(THREE_OF_A_KIND, Hand::signature(&cards));
+        }
 
-        let hand = Hand::parse(row);
+        #[test]
+        fn should_calculate_two_pair() {
+            let cards = cards("3KQ3Q", &Ruleset::jacks());
 
-        let expected_hand = Hand {
-            cards: vec![Number(8), Number(8), Card::Joker, Number(5), Number(5)],
-            hand_type: HandType::FullHouse,
-            bet: 684,
-        };
-        assert_eq!(expected_hand, hand);
+            assert_eq!(TWO_PAIR, Hand::signature(&cards));
+        }
+
+        #[test]
+        fn should_calculate_one_pair() {
+            let cards = cards("3KQ38", &Ruleset::jacks());
+
+            assert_eq!(ONE_PAIR, Hand::signature(&cards));
+        }
+
+        #[test]
+        fn should_calculate_high_card() {
+            let cards = cards("3KQ98", &Ruleset::jacks());
+
+            assert_eq!(HIGH_CARD, Hand::signature(&cards));
+        }
+
+        #[test]
+        fn should_parse_hand() {
+            let ruleset = Ruleset::jacks();
+
+            let hand = hand("T55Q5 684", &ruleset);
+
+            let expected_hand = Hand {
+                cards: cards("T55Q5", &ruleset),
+                hand_type: HandType {
+                    rank: 0,
+                    signature: THREE_OF_A_KIND.to_vec(),
+                },
+                bet: 684,
+                tie_break: TieBreak::Positional,
+                key: hand.key,
+            };
+            assert_eq!(expected_hand, hand);
+        }
+
+        #[test]
+        fn should_parse_jack_as_picture_card() {
+            let hand = hand("T55J5 684", &Ruleset::jacks());
+
+            assert_eq!(THREE_OF_A_KIND, hand.hand_type.signature);
+            assert!(!hand.cards[3].wild);
+        }
+
+        #[test]
+        fn should_order_jack_between_ten_and_queen() {
+            let ruleset = Ruleset::jacks();
+            let mut cards = cards("QJTA", &ruleset);
+
+            cards.sort();
+
+            assert_eq!(super::cards("TJQA", &ruleset), cards);
+        }
+
+        #[test]
+        fn should_calculate_total_winnings() {
+            let input = fs::read_to_string("resources/test.txt").unwrap();
+
+            assert_eq!(
+                Ok(6440),
+                parse_hands(&input, &Ruleset::jacks())
+                    .map(|hands| winnings(hands, |hand| hand.bet))
+            );
+        }
     }
 
-    #[test]
-    fn should_parse_hand_of_all_jokers() {
-        let row = "JJJJJ 684";
-
-        let hand = Hand::parse(row);
-
-        let expected_hand = Hand {
-            cards: vec![
-                Card::Joker,
-                Card::Joker,
-                Card::Joker,
-                Card::Joker,
-                Card::Joker,
-            ],
-            hand_type: HandType::FiveOfaKind,
-            bet: 684,
-        };
-        assert_eq!(expected_hand, hand);
+    mod jokers {
+        use super::*;
+
+        #[test]
+        fn should_order_hands_by_type_then_cards_with_jokers() {
+            let ruleset = Ruleset::jokers();
+            let one = hand("32T3K 0", &ruleset);
+            let two = hand("KJTJT 0", &ruleset);
+            let three = hand("KJKJT 0", &ruleset);
+            let four = hand("K2222 0", &ruleset);
+            let mut hands = vec![three.clone(), one.clone(), four.clone(), two.clone()];
+
+            hands.sort();
+
+            let expected = vec![one, two, three, four];
+            assert_eq!(expected, hands);
+        }
+
+        #[test]
+        fn should_parse_hand_with_joker_four_of_a_kind() {
+            let ruleset = Ruleset::jokers();
+
+            let hand = hand("T55J5 684", &ruleset);
+
+            let expected_hand = Hand {
+                cards: cards("T55J5", &ruleset),
+                hand_type: HandType {
+                    rank: 0,
+                    signature: FOUR_OF_A_KIND.to_vec(),
+                },
+                bet: 684,
+                tie_break: TieBreak::Positional,
+                key: hand.key,
+            };
+            assert_eq!(expected_hand, hand);
+        }
+
+        #[test]
+        fn should_parse_hand_with_joker_one_pair() {
+            let hand = hand("T58J2 684", &Ruleset::jokers());
+
+            assert_eq!(ONE_PAIR, hand.hand_type.signature);
+        }
+
+        #[test]
+        fn should_parse_hand_with_joker_three_of_a_kind() {
+            let hand = hand("TT8J2 684", &Ruleset::jokers());
+
+            assert_eq!(THREE_OF_A_KIND, hand.hand_type.signature);
+        }
+
+        #[test]
+        fn should_parse_hand_with_joker_five_of_a_kind() {
+            let hand = hand("TTTJT 684", &Ruleset::jokers());
+
+            assert_eq!(FIVE_OF_A_KIND, hand.hand_type.signature);
+        }
+
+        #[test]
+        fn should_parse_hand_with_joker_full_house() {
+            let hand = hand("88J55 684", &Ruleset::jokers());
+
+            assert_eq!(FULL_HOUSE, hand.hand_type.signature);
+        }
+
+        #[test]
+        fn should_parse_hand_of_all_jokers() {
+            let hand = hand("JJJJJ 684", &Ruleset::jokers());
+
+            assert_eq!(FIVE_OF_A_KIND, hand.hand_type.signature);
+            assert!(hand.cards.iter().all(|card| card.wild));
+        }
+
+        #[test]
+        fn should_parse_hand_of_four_jokers() {
+            let hand = hand("JJ5JJ 684", &Ruleset::jokers());
+
+            assert_eq!(FIVE_OF_A_KIND, hand.hand_type.signature);
+        }
+
+        #[test]
+        fn should_parse_hand_of_three_jokers() {
+            let hand = hand("JJ5J4 684", &Ruleset::jokers());
+
+            assert_eq!(FOUR_OF_A_KIND, hand.hand_type.signature);
+        }
+
+        #[test]
+        fn should_not_count_jokers_twice() {
+            let cards = cards("JJ234", &Ruleset::jokers());
+
+            assert_eq!(THREE_OF_A_KIND, Hand::signature(&cards));
+        }
+
+        #[test]
+        fn should_order_joker_below_two() {
+            let ruleset = Ruleset::jokers();
+
+            assert!(hand("JKKK2 0", &ruleset) < hand("QQQQ2 0", &ruleset));
+        }
+
+        #[test]
+        fn should_calculate_total_winnings() {
+            let input = fs::read_to_string("resources/test.txt").unwrap();
+
+            assert_eq!(
+                Ok(5905),
+                parse_hands(&input, &Ruleset::jokers())
+                    .map(|hands| winnings(hands, |hand| hand.bet))
+            );
+        }
     }
 
-    #[test]
-    fn should_parse_hand_of_four_jokers() {
-        let row = "JJ5JJ 684";
-
-        let hand = Hand::parse(row);
-
-        let expected_hand = Hand {
-            cards: vec![
-                Card::Joker,
-                Card::Joker,
-                Number(5),
-                Card::Joker,
-                Card::Joker,
-            ],
-            hand_type: HandType::FiveOfaKind,
-            bet: 684,
-        };
-        assert_eq!(expected_hand, hand);
+    mod poker {
+        use super::*;
+        use std::collections::HashSet;
+
+        fn rank(row: &str) -> PokerRank {
+            PokerHand::parse(row).unwrap().rank
+        }
+
+        fn category(row: &str) -> PokerCategory {
+            rank(row).category
+        }
+
+        #[test]
+        fn should_parse_poker_cards() {
+            let ruleset = Ruleset::jacks();
+
+            let ten = PokerCard::parse("10H", &ruleset).unwrap();
+            let also_ten = PokerCard::parse("TH", &ruleset).unwrap();
+            let ace = PokerCard::parse("AS", &ruleset).unwrap();
+
+            assert_eq!(ten, also_ten);
+            assert_eq!(8, ten.card.rank);
+            assert_eq!(Suit::Hearts, ten.suit);
+            assert_eq!(PokerRank::ACE, ace.card.rank);
+            assert!(PokerCard::parse("1H", &ruleset).is_err());
+            assert!(PokerCard::parse("AX", &ruleset).is_err());
+            assert!(PokerCard::parse("S", &ruleset).is_err());
+        }
+
+        #[test]
+        fn should_reject_invalid_poker_hands() {
+            assert_eq!(
+                Some(GameError::WrongHandSize(4)),
+                PokerHand::parse("AS KS QS JS 10").err()
+            );
+            assert_eq!(
+                Some(GameError::DuplicatePokerCard("AS".to_string())),
+                PokerHand::parse("AS KS QS JS AS 10").err()
+            );
+            assert_eq!(
+                Some(GameError::InvalidPokerCard("11S".to_string())),
+                PokerHand::parse("AS KS QS JS 11S 10").err()
+            );
+        }
+
+        #[test]
+        fn should_detect_each_category() {
+            assert_eq!(PokerCategory::StraightFlush, category("AS KS QS JS 10S 0"));
+            assert_eq!(PokerCategory::FourOfAKind, category("9C 9D 9H 9S 2D 0"));
+            assert_eq!(PokerCategory::FullHouse, category("2H 2D 2C KD KS 0"));
+            assert_eq!(PokerCategory::Flush, category("2H 7H 9H JH KH 0"));
+            assert_eq!(PokerCategory::Straight, category("6C 7D 8H 9S 10C 0"));
+            assert_eq!(PokerCategory::ThreeOfAKind, category("QC QD QH 4S 9C 0"));
+            assert_eq!(PokerCategory::TwoPair, category("9C 9D 4H 4S KC 0"));
+            assert_eq!(PokerCategory::OnePair, category("9C 9D 4H 3S KC 0"));
+            assert_eq!(PokerCategory::HighCard, category("3D 7C 9H JS AD 0"));
+        }
+
+        #[test]
+        fn should_rank_wheel_as_lowest_straight() {
+            let wheel = rank("AH 2D 3C 4S 5H 0");
+            let six_high = rank("2D 3C 4S 5H 6H 0");
+            let ace_high = rank("10D JC QS KH AH 0");
+
+            assert_eq!(PokerCategory::Straight, wheel.category);
+            assert!(wheel < six_high);
+            assert!(six_high < ace_high);
+            assert_eq!(PokerCategory::StraightFlush, category("AH 2H 3H 4H 5H 0"));
+            assert_eq!(PokerCategory::HighCard, category("QH KD AC 2S 3H 0"));
+        }
+
+        #[test]
+        fn should_break_ties_with_kickers() {
+            assert!(rank("8C 8D AH 4S 2C 0") > rank("8H 8S KC QD JH 0"));
+            assert!(rank("8C 8D AH 4S 3C 0") > rank("8H 8S AC 4D 2H 0"));
+            assert!(rank("KC KD 3H 3S 2C 0") > rank("KH KS 2C 2D AH 0"));
+            assert!(rank("KC KD 3H 3S 5C 0") > rank("KH KS 3C 3D 4H 0"));
+            assert!(rank("3C 3D 3H 2S 2C 0") > rank("2H 2S 2D AC AH 0"));
+            assert!(rank("AH 9H 7H 5H 3H 0") > rank("KD QD JD 9D 7D 0"));
+            assert_eq!(rank("AH KD 7C 5H 3S 0"), rank("AC KS 7D 5S 3H 0"));
+        }
+
+        #[test]
+        fn should_pick_best_five_of_seven() {
+            assert_eq!(PokerCategory::Flush, category("2H 7H 9H JH KH KD KC 0"));
+            assert_eq!(PokerCategory::FullHouse, category("2H 2D 2C KD KS KH 3C 0"));
+            assert_eq!(PokerCategory::Straight, category("AH 2D 3C 4S 5H 5D 9C 0"));
+            assert_eq!(rank("AH AD KC QS JH 0"), rank("AH AD 2C 3S KC QS JH 0"));
+            assert_eq!(vec![4], rank("2D 3C 4S 5H 6H AH 2C 0").tie_break);
+        }
+
+        #[test]
+        fn should_count_every_five_card_hand() {
+            let ruleset = Ruleset::jacks();
+            let deck: Vec<PokerCard> = ["C", "D", "H", "S"]
+                .iter()
+                .flat_map(|suit| {
+                    ruleset
+                        .ranks
+                        .iter()
+                        .map(move |rank| format!("{rank}{suit}"))
+                })
+                .map(|token| PokerCard::parse(&token, &ruleset).unwrap())
+                .collect();
+            let mut counts = [0; 9];
+            let mut distinct: HashSet<PokerRank> = HashSet::new();
+
+            for a in 0..deck.len() {
+                for b in a + 1..deck.len() {
+                    for c in b + 1..deck.len() {
+                        for d in c + 1..deck.len() {
+                            for e in d + 1..deck.len() {
+                                let hand = [deck[a], deck[b], deck[c], deck[d], deck[e]];
+                                let rank = PokerRank::evaluate(&hand);
+                                counts[rank.category as usize] += 1;
+                                distinct.insert(rank);
+                            }
+                        }
+                    }
+                }
+            }
+
+            assert_eq!(40, counts[PokerCategory::StraightFlush as usize]);
+            assert_eq!(624, counts[PokerCategory::FourOfAKind as usize]);
+            assert_eq!(3744, counts[PokerCategory::FullHouse as usize]);
+            assert_eq!(5108, counts[PokerCategory::Flush as usize]);
+            assert_eq!(10200, counts[PokerCategory::Straight as usize]);
+            assert_eq!(54912, counts[PokerCategory::ThreeOfAKind as usize]);
+            assert_eq!(123552, counts[PokerCategory::TwoPair as usize]);
+            assert_eq!(1098240, counts[PokerCategory::OnePair as usize]);
+            assert_eq!(1302540, counts[PokerCategory::HighCard as usize]);
+            assert_eq!(7462, distinct.len());
+        }
+
+        #[test]
+        fn should_calculate_poker_winnings() {
+            let input = fs::read_to_string("resources/poker.txt").unwrap();
+
+            assert_eq!(Ok(460), poker_winnings(&input));
+        }
     }
 
-    #[test]
-    fn should_parse_hand_of_three_jokers() {
-        let row = "JJ5J4 684";
+    mod sort_key {
+        use super::*;
+        use proptest::collection::vec;
+        use proptest::prelude::*;
+        use proptest::sample::select;
+        use std::collections::HashMap;
+
+        // The HashMap classifier and field by field comparison that the
+        // counting array and packed sort key replaced.
+        fn reference_signature(cards: &[Card]) -> Vec<u32> {
+            let mut counts: HashMap<char, u32> = HashMap::new();
+            let mut wild = 0;
+            for card in cards.iter() {
+                if card.wild {
+                    wild += 1;
+                } else {
+                    *counts.entry(card.symbol).or_insert(0) += 1;
+                }
+            }
 
-        let hand = Hand::parse(row);
+            let mut signature: Vec<u32> = counts.into_values().collect();
+            signature.sort_by(|a, b| b.cmp(a));
+            match signature.first_mut() {
+                Some(max_count) => *max_count += wild,
+                None if wild > 0 => signature.push(wild),
+                None => {}
+            }
+            signature
+        }
 
-        let expected_hand = Hand {
-            cards: vec![Card::Joker, Card::Joker, Number(5), Card::Joker, Number(4)],
-            hand_type: HandType::FourOfAKind,
-            bet: 684,
-        };
-        assert_eq!(expected_hand, hand);
+        fn reference_cmp(a: &Hand, b: &Hand) -> Ordering {
+            if a.hand_type != b.hand_type {
+                return a.hand_type.cmp(&b.hand_type);
+            }
+            match a.tie_break {
+                TieBreak::Positional => a.cards.cmp(&b.cards),
+                TieBreak::HighCard => a.high_cards().cmp(&b.high_cards()),
+            }
+        }
+
+        // The three symbol deck makes equal types and shared prefixes common.
+        fn rulesets() -> impl Strategy<Value = Ruleset> {
+            prop_oneof![
+                Just(Ruleset::jacks()),
+                Just(Ruleset::jokers()),
+                Just(Ruleset::new("XY23456789TQKA", "XY", TieBreak::HighCard).unwrap()),
+                Just(Ruleset::new("AJ2", "J", TieBreak::Positional).unwrap()),
+                Just(Ruleset::new("AJ2", "J", TieBreak::HighCard).unwrap()),
+            ]
+        }
+
+        fn hands() -> impl Strategy<Value = (Hand, Hand)> {
+            (rulesets(), 1..=Hand::MAX_CARDS)
+                .prop_flat_map(|(ruleset, size)| {
+                    let symbols = ruleset.ranks.clone();
+                    (
+                        Just(ruleset),
+                        vec(select(symbols.clone()), size),
+                        vec(select(symbols), size),
+                    )
+                })
+                .prop_map(|(ruleset, first, second)| {
+                    let first: String = first.into_iter().collect();
+                    let second: String = second.into_iter().collect();
+                    (
+                        hand(&format!("{first} 1"), &ruleset),
+                        hand(&format!("{second} 1"), &ruleset),
+                    )
+                })
+        }
+
+        #[test]
+        fn should_rank_by_table_before_signature() {
+            let table = HandTable::parse("1,1,1;3;2,1").unwrap();
+            let ruleset = Ruleset::jacks().with_hand_types(table);
+
+            assert!(hand("AAA 1", &ruleset) < hand("223 1", &ruleset));
+            assert!(hand("A23 1", &ruleset) < hand("222 1", &ruleset));
+        }
+
+        #[test]
+        fn should_order_signatures_of_different_lengths() {
+            let ruleset = Ruleset::jacks();
+
+            assert!(hand("AKQJ 1", &ruleset) < hand("23456 1", &ruleset));
+            assert!(hand("AK 1", &ruleset) < hand("234 1", &ruleset));
+            assert!(hand("AAAA 1", &ruleset) < hand("22223 1", &ruleset));
+        }
+
+        #[test]
+        fn should_recompute_stored_keys_for_generated_hands() {
+            for ruleset in [Ruleset::jacks(), Ruleset::jokers()] {
+                let input = generate_hands(1000, &ruleset, 7);
+
+                for hand in parse_hands(&input, &ruleset).unwrap() {
+                    let hand_type = ruleset.hand_types.classify(&hand.cards).unwrap();
+                    let key = Hand::sort_key(&hand_type, &hand.cards, ruleset.tie_break);
+                    assert_eq!(hand.key, key);
+                }
+            }
+        }
+
+        #[test]
+        fn should_reject_oversized_hands() {
+            let result = Hand::parse("222222222222222222222 1", &Ruleset::jacks());
+
+            assert_eq!(Some(GameError::TooManyCards(21)), result.err());
+        }
+
+        proptest! {
+            #[test]
+            fn should_match_reference_signature((first, second) in hands()) {
+                prop_assert_eq!(reference_signature(&first.cards), Hand::signature(&first.cards));
+                prop_assert_eq!(reference_signature(&second.cards), Hand::signature(&second.cards));
+            }
+
+            #[test]
+            fn should_match_reference_ordering((first, second) in hands()) {
+                prop_assert_eq!(reference_cmp(&first, &second), first.cmp(&second));
+                prop_assert_eq!(reference_cmp(&second, &first), second.cmp(&first));
+            }
+        }
     }
 }
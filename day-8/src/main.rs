@@ -1,90 +1,620 @@
-use std::collections::HashMap;
-use std::fs;
-use num::integer::lcm;
+use num::integer::Integer;
+use regex::Regex;
+use std::collections::{HashMap, HashSet};
+use std::{fmt, fs, process};
 
 fn main() {
     let args: Vec<String> = std::env::args().collect();
     let file_name = args.get(1).unwrap();
 
     let file = fs::read_to_string(file_name).unwrap();
-    let mut lines = file.lines();
-    let directions_line = lines.next().unwrap();
-    let directions = directions_line.chars().cycle();
-    lines.next(); // Skip whitespace
-    let nodes: HashMap<String, Node> = lines
-        .map(|line| Node::parse(line))
-        .map(|node| ((&node.label).clone(), node))
-        .collect();
-
-    let mut current = nodes.get(Node::START).unwrap();
-    let mut num_steps = 0u64;
-    for direction in directions {
-        current = step(&nodes, current, direction);
-        num_steps += 1;
-        if current.label == Node::END {
-            break;
-        }
-    }
-    println!("Part 1: {num_steps}");
-
-    let mut current_nodes: Vec<&Node> = nodes.values()
-        .filter(|node| node.is_start())
-        .collect();
-    println!("Num ghosts: {}", current_nodes.len());
-    let end_distances: u64 = current_nodes.iter().map(|node| {
-        let mut directions = directions_line.chars().cycle();
-        let mut current = *node;
-        let mut num_steps: u64 = 0;
-        while !current.is_end() {
-            current = step(&nodes, current, directions.next().unwrap());
-            num_steps += 1;
-        }
-        num_steps
-    }).reduce(|x, y| lcm(x, y)).unwrap();
-    println!("End distances: {:?}", end_distances);
-}
-
-fn step<'a>(nodes: &'a HashMap<String, Node>, current_node: &Node, direction: char) -> &'a Node {
-    let next_label = match direction {
-        'L' => &current_node.left,
-        'R' => &current_node.right,
-        _ => panic!("Unknown direction: {direction}"),
+    let (directions, network) = parse_input(&file).unwrap_or_else(|err| {
+        eprintln!("Invalid network in {file_name}: {err}");
+        process::exit(1);
+    });
+
+    let start = parse_flag(&args, "--start=", Matcher::Exact("AAA".into()));
+    let end = parse_flag(&args, "--end=", Matcher::Exact("ZZZ".into()));
+    let ghost_start = parse_flag(&args, "--ghost-start=", Matcher::Suffix("A".into()));
+    let ghost_end = parse_flag(&args, "--ghost-end=", Matcher::Suffix("Z".into()));
+
+    let ghosts = network.select(&ghost_start);
+    let ghost_ends = network.mark(&ghost_end);
+    let cycles = GhostCycle::detect_all(&network, &ghosts, &directions, &ghost_ends);
+
+    if let Some(dot_file) = args.iter().find_map(|arg| arg.strip_prefix("--dot=")) {
+        let overlays: Vec<(usize, &GhostCycle)> = if args.iter().any(|arg| arg == "--ghost-paths") {
+            ghosts.iter().copied().zip(cycles.iter()).collect()
+        } else {
+            vec![]
+        };
+        let is_start: Vec<bool> = network
+            .mark(&start)
+            .iter()
+            .zip(network.mark(&ghost_start))
+            .map(|(part_1, part_2)| *part_1 || part_2)
+            .collect();
+        let is_end: Vec<bool> = network
+            .mark(&end)
+            .iter()
+            .zip(ghost_ends.iter())
+            .map(|(part_1, part_2)| *part_1 || *part_2)
+            .collect();
+        let dot = render_dot(&network, &directions, &is_start, &is_end, &overlays);
+        fs::write(dot_file, dot).unwrap_or_else(|err| {
+            eprintln!("Could not write {dot_file}: {err}");
+            process::exit(1);
+        });
+    }
+
+    let starts = network.select(&start);
+    let ends = network.mark(&end);
+    if starts.is_empty() {
+        eprintln!("Skipping part 1: no node matches {start}");
+    }
+    for id in starts.iter() {
+        let num_steps = network
+            .first_end(*id, &directions, &ends)
+            .unwrap_or_else(|| {
+                eprintln!(
+                    "Invalid network in {file_name}: {}",
+                    GhostError::Unreachable(network.label(*id).to_string())
+                );
+                process::exit(1);
+            });
+        if starts.len() == 1 {
+            println!("Part 1: {num_steps}");
+        } else {
+            println!("Part 1 from {}: {num_steps}", network.label(*id));
+        }
+    }
+
+    println!("Num ghosts: {}", ghosts.len());
+    for (start, cycle) in ghosts.iter().zip(cycles.iter()) {
+        println!("Ghost {}: {cycle}", network.label(*start));
+    }
+    let end_distances = first_common_end(&cycles).unwrap_or_else(|err| {
+        eprintln!("Ghosts in {file_name} never meet: {err}");
+        process::exit(1);
+    });
+    let method = if cycles.iter().all(GhostCycle::fits_lcm) {
+        "LCM shortcut holds"
+    } else {
+        "LCM shortcut does not hold, solved by CRT"
     };
-    return nodes.get(next_label).unwrap();
+    println!("Part 2: {end_distances} ({method})");
 }
 
-#[derive(Debug, Eq, PartialEq)]
-struct Node {
-    label: String,
-    left: String,
-    right: String,
+fn parse_flag(args: &[String], prefix: &str, default: Matcher) -> Matcher {
+    match args.iter().find_map(|arg| arg.strip_prefix(prefix)) {
+        Some(value) => Matcher::parse(value).unwrap_or_else(|err| {
+            eprintln!("{err}");
+            process::exit(1);
+        }),
+        None => default,
+    }
 }
 
-impl Node {
-    fn is_start(&self) -> bool {
-        self.label.ends_with('A')
+// The first line holds the directions and every other non-blank line a node.
+fn parse_input(input: &str) -> Result<(Vec<Direction>, Network), NetworkError> {
+    let mut lines = input.lines();
+    let directions = lines
+        .next()
+        .ok_or(NetworkError::MissingDirections)?
+        .trim()
+        .chars()
+        .map(Direction::parse)
+        .collect::<Result<Vec<Direction>, NetworkError>>()?;
+    if directions.is_empty() {
+        return Err(NetworkError::MissingDirections);
+    }
+    let nodes = lines
+        .filter(|line| !line.trim().is_empty())
+        .map(Node::parse)
+        .collect::<Result<Vec<Node>, NetworkError>>()?;
+    Ok((directions, Network::new(&nodes)?))
+}
+
+#[derive(Debug, PartialEq, Eq)]
+enum NetworkError {
+    MissingDirections,
+    InvalidDirection(char),
+    InvalidNode(String),
+    DuplicateNode(String),
+    UnknownNode(String),
+}
+
+impl fmt::Display for NetworkError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            NetworkError::MissingDirections => write!(f, "the first line has no directions"),
+            NetworkError::InvalidDirection(direction) => {
+                write!(f, "'{direction}' is not a direction (expected L or R)")
+            }
+            NetworkError::InvalidNode(row) => {
+                write!(f, "'{row}' is not of the form LABEL = (LEFT, RIGHT)")
+            }
+            NetworkError::DuplicateNode(label) => write!(f, "node {label} is defined twice"),
+            NetworkError::UnknownNode(label) => {
+                write!(f, "node {label} is referenced but never defined")
+            }
+        }
     }
+}
 
-    fn is_end(&self) -> bool {
-        self.label.ends_with('Z')
+#[derive(Debug, Eq, PartialEq, Copy, Clone, Hash)]
+enum Direction {
+    Left,
+    Right,
+}
+
+impl fmt::Display for Direction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Direction::Left => write!(f, "L"),
+            Direction::Right => write!(f, "R"),
+        }
     }
 }
 
+impl Direction {
+    fn parse(direction: char) -> Result<Direction, NetworkError> {
+        match direction {
+            'L' => Ok(Direction::Left),
+            'R' => Ok(Direction::Right),
+            _ => Err(NetworkError::InvalidDirection(direction)),
+        }
+    }
+}
+
+// Labels are interned to dense ids in definition order, so a step is a
+// single index into the adjacency table rather than a string lookup.
+#[derive(Debug)]
+struct Network {
+    labels: Vec<String>,
+    edges: Vec<[usize; 2]>,
+}
+
+impl Network {
+    fn new(nodes: &[Node]) -> Result<Network, NetworkError> {
+        let mut ids = HashMap::with_capacity(nodes.len());
+        for (id, node) in nodes.iter().enumerate() {
+            if ids.insert(node.label.clone(), id).is_some() {
+                return Err(NetworkError::DuplicateNode(node.label.clone()));
+            }
+        }
+        let id = |label: &String| {
+            ids.get(label)
+                .copied()
+                .ok_or_else(|| NetworkError::UnknownNode(label.clone()))
+        };
+        let edges = nodes
+            .iter()
+            .map(|node| Ok([id(&node.left)?, id(&node.right)?]))
+            .collect::<Result<Vec<[usize; 2]>, NetworkError>>()?;
+        let labels = nodes.iter().map(|node| node.label.clone()).collect();
+        Ok(Network { labels, edges })
+    }
+
+    fn len(&self) -> usize {
+        self.labels.len()
+    }
+
+    fn label(&self, id: usize) -> &str {
+        &self.labels[id]
+    }
+
+    fn step(&self, id: usize, direction: Direction) -> usize {
+        self.edges[id][direction as usize]
+    }
+
+    // The nodes visited over the first `num_steps` steps, starting node first.
+    fn walk(&self, start: usize, directions: &[Direction], num_steps: u64) -> Vec<usize> {
+        let mut path = vec![start];
+        for direction in directions.iter().cycle().take(num_steps as usize) {
+            path.push(self.step(*path.last().unwrap(), *direction));
+        }
+        path
+    }
+
+    // Steps to the first end node reached after at least one step. There are
+    // only so many (node, instruction index) states, and once they have all
+    // had a chance to appear the walk is repeating itself, so giving up there
+    // catches an unreachable end without keeping a table of states.
+    fn first_end(&self, start: usize, directions: &[Direction], ends: &[bool]) -> Option<u64> {
+        let num_states = (self.len() * directions.len()) as u64;
+        let mut current = start;
+        for (num_steps, direction) in (1..=num_states).zip(directions.iter().cycle()) {
+            current = self.step(current, *direction);
+            if ends[current] {
+                return Some(num_steps);
+            }
+        }
+        None
+    }
+
+    // Ids of the nodes whose labels match, in label order.
+    fn select(&self, matcher: &Matcher) -> Vec<usize> {
+        let mut ids: Vec<usize> = (0..self.len())
+            .filter(|id| matcher.matches(self.label(*id)))
+            .collect();
+        ids.sort_by_key(|id| self.label(*id));
+        ids
+    }
+
+    // Whether each node, by id, matches.
+    fn mark(&self, matcher: &Matcher) -> Vec<bool> {
+        self.labels
+            .iter()
+            .map(|label| matcher.matches(label))
+            .collect()
+    }
+}
+
+#[derive(Debug)]
+enum Matcher {
+    Exact(String),
+    Prefix(String),
+    Suffix(String),
+    Pattern(Regex),
+}
+
+impl Matcher {
+    // KIND:VALUE, e.g. "exact:AAA", "prefix:1", "suffix:Z" or "regex:^.[0-9]A$".
+    fn parse(value: &str) -> Result<Matcher, MatcherError> {
+        let (kind, argument) = value
+            .split_once(':')
+            .ok_or_else(|| MatcherError::UnknownKind(value.to_string()))?;
+        match kind {
+            "exact" => Ok(Matcher::Exact(argument.to_string())),
+            "prefix" => Ok(Matcher::Prefix(argument.to_string())),
+            "suffix" => Ok(Matcher::Suffix(argument.to_string())),
+            "regex" => Regex::new(argument)
+                .map(Matcher::Pattern)
+                .map_err(|err| MatcherError::InvalidRegex(err.to_string())),
+            _ => Err(MatcherError::UnknownKind(value.to_string())),
+        }
+    }
+
+    fn matches(&self, label: &str) -> bool {
+        match self {
+            Matcher::Exact(expected) => label == expected,
+            Matcher::Prefix(prefix) => label.starts_with(prefix.as_str()),
+            Matcher::Suffix(suffix) => label.ends_with(suffix.as_str()),
+            Matcher::Pattern(regex) => regex.is_match(label),
+        }
+    }
+}
+
+impl fmt::Display for Matcher {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Matcher::Exact(expected) => write!(f, "exact:{expected}"),
+            Matcher::Prefix(prefix) => write!(f, "prefix:{prefix}"),
+            Matcher::Suffix(suffix) => write!(f, "suffix:{suffix}"),
+            Matcher::Pattern(regex) => write!(f, "regex:{regex}"),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+enum MatcherError {
+    UnknownKind(String),
+    InvalidRegex(String),
+}
+
+impl fmt::Display for MatcherError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MatcherError::UnknownKind(value) => write!(
+                f,
+                "'{value}' is not a node predicate (expected exact:, prefix:, suffix: or regex:)"
+            ),
+            MatcherError::InvalidRegex(err) => write!(f, "invalid regex: {err}"),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+enum GhostError {
+    NoGhosts,
+    NoSolution,
+    Unreachable(String),
+    Overflow,
+    TooManyCongruences(usize),
+}
+
+impl fmt::Display for GhostError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GhostError::NoGhosts => write!(f, "there are no start nodes"),
+            GhostError::NoSolution => {
+                write!(f, "no step puts every ghost on an end node at once")
+            }
+            GhostError::Unreachable(start) => {
+                write!(f, "no end node can be reached from {start}")
+            }
+            GhostError::Overflow => write!(f, "the first common end does not fit in 64 bits"),
+            GhostError::TooManyCongruences(count) => write!(
+                f,
+                "combining every ghost's cycle ends needs {count} congruences (limit {MAX_CONGRUENCES})"
+            ),
+        }
+    }
+}
+
+// A ghost's walk is fixed by its (node, instruction index) state, so it must
+// eventually repeat. Steps before the first repeated state form the tail, and
+// everything after it loops with the cycle length. End offsets are step
+// counts at which the ghost stands on an end node: those in the tail happen
+// once, those in the cycle recur every `length` steps.
+#[derive(Debug, PartialEq, Eq)]
+struct GhostCycle {
+    tail: u64,
+    length: u64,
+    tail_ends: Vec<u64>,
+    cycle_ends: Vec<u64>,
+}
+
+impl GhostCycle {
+    // Holds one slot per (node, instruction index) state: the step it was
+    // first seen at plus one, so that zero means unseen. The table is shared
+    // by every ghost and only the slots a walk touched are cleared after it,
+    // so each ghost costs its own walk rather than the whole state space.
+    fn detect_all(
+        network: &Network,
+        starts: &[usize],
+        directions: &[Direction],
+        ends: &[bool],
+    ) -> Vec<GhostCycle> {
+        let mut seen = vec![0u64; network.len() * directions.len()];
+        let mut visited = vec![];
+        let mut cycles = Vec::with_capacity(starts.len());
+        for start in starts.iter() {
+            let mut end_steps = vec![];
+            let mut current = *start;
+            let mut num_steps = 0u64;
+            let first = loop {
+                let index = (num_steps % directions.len() as u64) as usize;
+                let state = current * directions.len() + index;
+                if seen[state] > 0 {
+                    break seen[state] - 1;
+                }
+                seen[state] = num_steps + 1;
+                visited.push(state);
+                if ends[current] {
+                    end_steps.push(num_steps);
+                }
+                current = network.step(current, directions[index]);
+                num_steps += 1;
+            };
+            for state in visited.drain(..) {
+                seen[state] = 0;
+            }
+            let (tail_ends, cycle_ends) = end_steps.iter().partition(|end| **end < first);
+            cycles.push(GhostCycle {
+                tail: first,
+                length: num_steps - first,
+                tail_ends,
+                cycle_ends,
+            });
+        }
+        cycles
+    }
+
+    fn is_end_at(&self, num_steps: u64) -> bool {
+        if num_steps < self.tail {
+            self.tail_ends.contains(&num_steps)
+        } else {
+            let offset = self.tail + (num_steps - self.tail) % self.length;
+            self.cycle_ends.contains(&offset)
+        }
+    }
+
+    // Taking the LCM of first-hit distances is only right when the ghost
+    // reaches a single end exactly once per cycle, first at the cycle length.
+    fn fits_lcm(&self) -> bool {
+        self.tail_ends.is_empty() && self.cycle_ends == [self.length]
+    }
+}
+
+impl fmt::Display for GhostCycle {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "tail {}, cycle {}, ends at {:?}",
+            self.tail,
+            self.length,
+            [self.tail_ends.as_slice(), self.cycle_ends.as_slice()].concat()
+        )
+    }
+}
+
+// Each ghost multiplies the candidate congruences by its number of cycle ends,
+// so inputs with many ends per ghost are refused rather than ground through.
+const MAX_CONGRUENCES: usize = 1 << 16;
+
+// The first step count at which every ghost is on an end node. Below the
+// longest tail only that ghost's one-off ends can work, so they are checked
+// directly; beyond it every ghost is cycling and each choice of cycle end is
+// a congruence, combined with the Chinese Remainder Theorem.
+fn first_common_end(cycles: &[GhostCycle]) -> Result<u64, GhostError> {
+    let longest = cycles
+        .iter()
+        .max_by_key(|cycle| cycle.tail)
+        .ok_or(GhostError::NoGhosts)?;
+    if cycles.iter().all(GhostCycle::fits_lcm) {
+        return cycles.iter().try_fold(1u64, |total, cycle| {
+            (total / total.gcd(&cycle.length))
+                .checked_mul(cycle.length)
+                .ok_or(GhostError::Overflow)
+        });
+    }
+    if let Some(end) = longest
+        .tail_ends
+        .iter()
+        .find(|end| cycles.iter().all(|cycle| cycle.is_end_at(**end)))
+    {
+        return Ok(*end);
+    }
+
+    let mut congruences = HashSet::from([(0u64, 1u64)]);
+    for cycle in cycles.iter() {
+        let count = congruences.len().saturating_mul(cycle.cycle_ends.len());
+        if count > MAX_CONGRUENCES {
+            return Err(GhostError::TooManyCongruences(count));
+        }
+        let mut combined = HashSet::with_capacity(count);
+        for congruence in congruences.iter() {
+            for end in cycle.cycle_ends.iter() {
+                let residue = (end % cycle.length, cycle.length);
+                if let Some(solution) = crt(*congruence, residue)? {
+                    combined.insert(solution);
+                }
+            }
+        }
+        congruences = combined;
+    }
+
+    let ends = congruences
+        .iter()
+        .map(|(residue, modulus)| {
+            if *residue >= longest.tail {
+                Ok(*residue)
+            } else {
+                (longest.tail - residue)
+                    .div_ceil(*modulus)
+                    .checked_mul(*modulus)
+                    .and_then(|offset| offset.checked_add(*residue))
+                    .ok_or(GhostError::Overflow)
+            }
+        })
+        .collect::<Result<Vec<u64>, GhostError>>()?;
+    ends.into_iter().min().ok_or(GhostError::NoSolution)
+}
+
+// Combines x ≡ a (mod m) and x ≡ b (mod n), whose moduli need not be coprime,
+// into a single congruence modulo lcm(m, n), if one exists. The lcm must fit
+// in a u64; once it does, every intermediate below fits in 128 bits.
+fn crt((a, m): (u64, u64), (b, n): (u64, u64)) -> Result<Option<(u64, u64)>, GhostError> {
+    let gcd = (m as i128).extended_gcd(&(n as i128));
+    let difference = b as i128 - a as i128;
+    if difference % gcd.gcd != 0 {
+        return Ok(None);
+    }
+    let step = (n as i128 / gcd.gcd) as u128;
+    let modulus = (m as u128 / gcd.gcd as u128) * n as u128;
+    let modulus = u64::try_from(modulus).map_err(|_| GhostError::Overflow)?;
+    // k = (b - a) / gcd * x (mod n / gcd), with both factors reduced first.
+    let quotient = (difference / gcd.gcd).mod_floor(&(step as i128)) as u128;
+    let inverse = gcd.x.mod_floor(&(step as i128)) as u128;
+    let k = quotient * inverse % step;
+    let residue = (a as u128 + m as u128 * k) % modulus as u128;
+    Ok(Some((residue as u64, modulus)))
+}
+
+// Graphviz DOT for the whole network, with each edge labelled by its
+// direction. Start nodes are filled green, end nodes red and nodes that are
+// both gold. Each overlaid ghost gets its own colour: its tail is drawn
+// dashed and one full cycle solid, so a ghost whose cycle holds several ends
+// or whose first end comes before its cycle starts stands out.
+fn render_dot(
+    network: &Network,
+    directions: &[Direction],
+    is_start: &[bool],
+    is_end: &[bool],
+    overlays: &[(usize, &GhostCycle)],
+) -> String {
+    const COLOURS: [&str; 8] = [
+        "#1f77b4", "#ff7f0e", "#2ca02c", "#d62728", "#9467bd", "#8c564b", "#e377c2", "#17becf",
+    ];
+    let quote = |id: usize| {
+        format!(
+            "\"{}\"",
+            network.label(id).replace('\\', "\\\\").replace('"', "\\\"")
+        )
+    };
+
+    let mut dot = String::from("digraph network {\n");
+    for id in 0..network.len() {
+        let fill = match (is_start[id], is_end[id]) {
+            (true, true) => " [style=filled, fillcolor=gold]",
+            (true, false) => " [style=filled, fillcolor=palegreen]",
+            (false, true) => " [style=filled, fillcolor=lightcoral]",
+            (false, false) => "",
+        };
+        dot += &format!("  {}{fill};\n", quote(id));
+    }
+    for id in 0..network.len() {
+        for direction in [Direction::Left, Direction::Right] {
+            dot += &format!(
+                "  {} -> {} [label=\"{direction}\"];\n",
+                quote(id),
+                quote(network.step(id, direction))
+            );
+        }
+    }
+    for (i, (start, cycle)) in overlays.iter().enumerate() {
+        let colour = COLOURS[i % COLOURS.len()];
+        dot += &format!("  // Ghost {}: {cycle}\n", network.label(*start));
+        let path = network.walk(*start, directions, cycle.tail + cycle.length);
+        let mut drawn = HashSet::new();
+        for (step, pair) in path.windows(2).enumerate() {
+            let direction = directions[step % directions.len()];
+            let style = if (step as u64) < cycle.tail {
+                "dashed"
+            } else {
+                "solid"
+            };
+            if !drawn.insert((pair[0], direction, style)) {
+                continue;
+            }
+            dot += &format!(
+                "  {} -> {} [label=\"{direction}\", color=\"{colour}\", fontcolor=\"{colour}\", penwidth=2, style={style}];\n",
+                quote(pair[0]),
+                quote(pair[1])
+            );
+        }
+    }
+    dot += "}\n";
+    dot
+}
+
+#[derive(Debug, Eq, PartialEq)]
+struct Node {
+    label: String,
+    left: String,
+    right: String,
+}
+
 impl Node {
-    const START: &'static str = "AAA";
-    const END: &'static str = "ZZZ";
-
-    fn parse(row: &str) -> Node {
-        let parts: Vec<&str> = row.split(" = ").collect();
-        let label = parts[0].trim();
-        let forks = parts[1].trim();
-        let left = &forks[1..=3];
-        let right = &forks[6..=8];
-        Node {
+    // LABEL = (LEFT, RIGHT), where a label is any run of characters other
+    // than whitespace and the punctuation of the row itself.
+    fn parse(row: &str) -> Result<Node, NetworkError> {
+        let invalid = || NetworkError::InvalidNode(row.to_string());
+        let (label, forks) = row.split_once('=').ok_or_else(invalid)?;
+        let (left, right) = forks
+            .trim()
+            .strip_prefix('(')
+            .and_then(|forks| forks.strip_suffix(')'))
+            .and_then(|forks| forks.split_once(','))
+            .ok_or_else(invalid)?;
+        let [label, left, right] = [label, left, right].map(str::trim);
+        if ![label, left, right].into_iter().all(Node::is_label) {
+            return Err(invalid());
+        }
+        Ok(Node {
             label: label.to_string(),
             left: left.to_string(),
             right: right.to_string(),
-        }
+        })
+    }
+
+    fn is_label(label: &str) -> bool {
+        !label.is_empty()
+            && !label
+                .chars()
+                .any(|c| c.is_whitespace() || "=(),".contains(c))
     }
 }
 
@@ -92,11 +622,26 @@ impl Node {
 mod tests {
     use super::*;
 
+    fn parse_network(rows: &[&str]) -> Network {
+        let nodes: Vec<Node> = rows.iter().map(|row| Node::parse(row).unwrap()).collect();
+        Network::new(&nodes).unwrap()
+    }
+
+    fn detect(network: &Network, start: &str, directions: &str) -> GhostCycle {
+        let directions: Vec<Direction> = directions
+            .chars()
+            .map(|direction| Direction::parse(direction).unwrap())
+            .collect();
+        let ends = network.mark(&Matcher::Suffix("Z".into()));
+        let start = network.select(&Matcher::Exact(start.into()))[0];
+        GhostCycle::detect_all(network, &[start], &directions, &ends).remove(0)
+    }
+
     #[test]
     fn should_parse_node() {
         let row = "BBB = (AAA, ZZZ)";
 
-        let node = Node::parse(row);
+        let node = Node::parse(row).unwrap();
 
         let expected = Node {
             label: "BBB".into(),
@@ -105,4 +650,400 @@ mod tests {
         };
         assert_eq!(expected, node);
     }
+
+    #[test]
+    fn should_parse_labels_of_any_length() {
+        let row = "start_1=( a ,LONGER-LABEL )";
+
+        let node = Node::parse(row).unwrap();
+
+        assert_eq!("start_1", node.label);
+        assert_eq!("a", node.left);
+        assert_eq!("LONGER-LABEL", node.right);
+    }
+
+    #[test]
+    fn should_reject_malformed_nodes() {
+        for row in [
+            "AAA (BBB, CCC)",
+            "AAA = BBB, CCC",
+            "AAA = (BBB)",
+            " = (BBB, CCC)",
+        ] {
+            assert_eq!(
+                Err(NetworkError::InvalidNode(row.to_string())),
+                Node::parse(row)
+            );
+        }
+    }
+
+    #[test]
+    fn should_intern_labels_as_dense_ids() {
+        let network = parse_network(&["AAA = (BB, Z)", "BB = (Z, AAA)", "Z = (Z, Z)"]);
+
+        assert_eq!(vec![1], network.select(&Matcher::Exact("BB".into())));
+        assert_eq!("Z", network.label(2));
+        assert_eq!(2, network.step(0, Direction::Right));
+        assert_eq!(0, network.step(1, Direction::Right));
+    }
+
+    #[test]
+    fn should_reject_unknown_and_duplicate_nodes() {
+        let unknown = [Node::parse("AAA = (BBB, AAA)").unwrap()];
+        let duplicate = [
+            Node::parse("AAA = (AAA, AAA)").unwrap(),
+            Node::parse("AAA = (AAA, AAA)").unwrap(),
+        ];
+
+        assert_eq!(
+            Some(NetworkError::UnknownNode("BBB".into())),
+            Network::new(&unknown).err()
+        );
+        assert_eq!(
+            Some(NetworkError::DuplicateNode("AAA".into())),
+            Network::new(&duplicate).err()
+        );
+        assert_eq!(
+            Some(NetworkError::InvalidDirection('X')),
+            parse_input("LXR\n\nAAA = (AAA, AAA)").err()
+        );
+    }
+
+    #[test]
+    fn should_match_node_predicates() {
+        let exact = Matcher::parse("exact:AAA").unwrap();
+        let prefix = Matcher::parse("prefix:11").unwrap();
+        let suffix = Matcher::parse("suffix:Z").unwrap();
+        let regex = Matcher::parse("regex:^[0-9]+A$").unwrap();
+
+        assert!(exact.matches("AAA") && !exact.matches("AAAA"));
+        assert!(prefix.matches("11Z") && !prefix.matches("211"));
+        assert!(suffix.matches("22Z") && !suffix.matches("Z2"));
+        assert!(regex.matches("123A") && !regex.matches("X23A"));
+        assert_eq!("regex:^[0-9]+A$", regex.to_string());
+    }
+
+    #[test]
+    fn should_reject_unknown_predicates() {
+        assert_eq!(
+            Some(MatcherError::UnknownKind("glob:*A".into())),
+            Matcher::parse("glob:*A").err()
+        );
+        assert_eq!(
+            Some(MatcherError::UnknownKind("AAA".into())),
+            Matcher::parse("AAA").err()
+        );
+        assert!(matches!(
+            Matcher::parse("regex:(").err(),
+            Some(MatcherError::InvalidRegex(_))
+        ));
+    }
+
+    #[test]
+    fn should_select_nodes_in_label_order() {
+        let network = parse_network(&["B1 = (A1, A1)", "A1 = (B1, C2)", "C2 = (C2, C2)"]);
+
+        assert_eq!(vec![1, 0], network.select(&Matcher::Suffix("1".into())));
+        assert_eq!(
+            vec![false, false, true],
+            network.mark(&Matcher::Prefix("C".into()))
+        );
+    }
+
+    #[test]
+    fn should_find_next_end_after_start() {
+        let network = parse_network(&["AAA = (BBB, BBB)", "BBB = (AAA, ZZZ)", "ZZZ = (ZZZ, ZZZ)"]);
+        let directions = [Direction::Left, Direction::Left, Direction::Right];
+        let ends = network.mark(&Matcher::Exact("ZZZ".into()));
+
+        assert_eq!(Some(6), network.first_end(0, &directions, &ends));
+        assert_eq!(Some(1), network.first_end(2, &directions, &ends));
+    }
+
+    #[test]
+    fn should_detect_unreachable_end() {
+        let network = parse_network(&["AAA = (BBB, BBB)", "BBB = (AAA, AAA)", "ZZZ = (ZZZ, AAA)"]);
+        let directions = [Direction::Left, Direction::Right];
+        let ends = network.mark(&Matcher::Exact("ZZZ".into()));
+
+        assert_eq!(None, network.first_end(0, &directions, &ends));
+    }
+
+    #[test]
+    fn should_walk_network() {
+        let network = parse_network(&["AAA = (BBB, AAA)", "BBB = (AAA, ZZZ)", "ZZZ = (ZZZ, ZZZ)"]);
+        let directions = [Direction::Left, Direction::Right];
+
+        assert_eq!(vec![0, 1, 2, 2], network.walk(0, &directions, 3));
+    }
+
+    #[test]
+    fn should_render_dot_with_ghost_paths() {
+        let network = parse_network(&["A\"1 = (B, Z)", "B = (Z, Z)", "Z = (B, B)"]);
+        let directions = [Direction::Left];
+        let is_start = network.mark(&Matcher::Prefix("A".into()));
+        let is_end = network.mark(&Matcher::Exact("Z".into()));
+        let cycle = GhostCycle::detect_all(&network, &[0], &directions, &is_end).remove(0);
+
+        let dot = render_dot(&network, &directions, &is_start, &is_end, &[(0, &cycle)]);
+
+        let expected = [
+            "digraph network {",
+            "  \"A\\\"1\" [style=filled, fillcolor=palegreen];",
+            "  \"B\";",
+            "  \"Z\" [style=filled, fillcolor=lightcoral];",
+            "  \"A\\\"1\" -> \"B\" [label=\"L\"];",
+            "  \"A\\\"1\" -> \"Z\" [label=\"R\"];",
+            "  \"B\" -> \"Z\" [label=\"L\"];",
+            "  \"B\" -> \"Z\" [label=\"R\"];",
+            "  \"Z\" -> \"B\" [label=\"L\"];",
+            "  \"Z\" -> \"B\" [label=\"R\"];",
+            "  // Ghost A\"1: tail 1, cycle 2, ends at [2]",
+            "  \"A\\\"1\" -> \"B\" [label=\"L\", color=\"#1f77b4\", fontcolor=\"#1f77b4\", penwidth=2, style=dashed];",
+            "  \"B\" -> \"Z\" [label=\"L\", color=\"#1f77b4\", fontcolor=\"#1f77b4\", penwidth=2, style=solid];",
+            "  \"Z\" -> \"B\" [label=\"L\", color=\"#1f77b4\", fontcolor=\"#1f77b4\", penwidth=2, style=solid];",
+            "}",
+        ];
+        assert_eq!(expected.join("\n") + "\n", dot);
+    }
+
+    // Every three letter label, each forking to two others picked by a
+    // xorshift generator so that runs repeat.
+    fn generate_network() -> Network {
+        let labels: Vec<String> = (0..26 * 26 * 26)
+            .map(|id: u32| {
+                [id / 676, id / 26 % 26, id % 26]
+                    .map(|letter| char::from(b'A' + letter as u8))
+                    .iter()
+                    .collect()
+            })
+            .collect();
+        let mut state = 7u64;
+        let mut next = || {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            labels[(state % labels.len() as u64) as usize].clone()
+        };
+        let nodes: Vec<Node> = labels
+            .iter()
+            .map(|label| Node {
+                label: label.clone(),
+                left: next(),
+                right: next(),
+            })
+            .collect();
+        Network::new(&nodes).unwrap()
+    }
+
+    #[test]
+    fn should_detect_cycles_on_large_network() {
+        let network = generate_network();
+        let directions: Vec<Direction> = (0..281)
+            .map(|i| [Direction::Left, Direction::Right][i * i % 7 % 2])
+            .collect();
+        let ends = network.mark(&Matcher::Suffix("Z".into()));
+        let ghosts = network.select(&Matcher::Suffix("A".into()));
+
+        assert_eq!(17_576, network.len());
+        assert_eq!(676, ghosts.len());
+        let cycles = GhostCycle::detect_all(&network, &ghosts, &directions, &ends);
+        for (start, cycle) in ghosts.into_iter().zip(cycles) {
+            let path = network.walk(start, &directions, cycle.tail + 2 * cycle.length);
+            for (num_steps, id) in path.iter().enumerate() {
+                assert_eq!(ends[*id], cycle.is_end_at(num_steps as u64));
+            }
+        }
+    }
+
+    #[test]
+    fn should_detect_ghost_cycles() {
+        let network = parse_network(&[
+            "11A = (11B, XXX)",
+            "11B = (XXX, 11Z)",
+            "11Z = (11B, XXX)",
+            "22A = (22B, XXX)",
+            "22B = (22C, 22C)",
+            "22C = (22Z, 22Z)",
+            "22Z = (22B, 22B)",
+            "XXX = (XXX, XXX)",
+        ]);
+
+        let first = detect(&network, "11A", "LR");
+        let second = detect(&network, "22A", "LR");
+
+        let expected_first = GhostCycle {
+            tail: 1,
+            length: 2,
+            tail_ends: vec![],
+            cycle_ends: vec![2],
+        };
+        let expected_second = GhostCycle {
+            tail: 1,
+            length: 6,
+            tail_ends: vec![],
+            cycle_ends: vec![3, 6],
+        };
+        assert_eq!(expected_first, first);
+        assert_eq!(expected_second, second);
+        assert!(first.fits_lcm());
+        assert!(!second.fits_lcm());
+        assert_eq!(Ok(6), first_common_end(&[first, second]));
+    }
+
+    #[test]
+    fn should_use_lcm_when_it_holds() {
+        let cycles = [
+            GhostCycle {
+                tail: 2,
+                length: 4,
+                tail_ends: vec![],
+                cycle_ends: vec![4],
+            },
+            GhostCycle {
+                tail: 3,
+                length: 6,
+                tail_ends: vec![],
+                cycle_ends: vec![6],
+            },
+        ];
+
+        assert_eq!(Ok(12), first_common_end(&cycles));
+    }
+
+    #[test]
+    fn should_solve_offset_cycles_with_crt() {
+        // Ends at 3, 8, 13, ... and at 4, 11, 18, ...; the LCM would say 35.
+        let cycles = [
+            GhostCycle {
+                tail: 0,
+                length: 5,
+                tail_ends: vec![],
+                cycle_ends: vec![3],
+            },
+            GhostCycle {
+                tail: 1,
+                length: 7,
+                tail_ends: vec![],
+                cycle_ends: vec![4],
+            },
+        ];
+
+        assert_eq!(Ok(18), first_common_end(&cycles));
+    }
+
+    #[test]
+    fn should_meet_in_tail() {
+        let cycles = [
+            GhostCycle {
+                tail: 5,
+                length: 4,
+                tail_ends: vec![2],
+                cycle_ends: vec![7],
+            },
+            GhostCycle {
+                tail: 0,
+                length: 2,
+                tail_ends: vec![],
+                cycle_ends: vec![0],
+            },
+        ];
+
+        assert_eq!(Ok(2), first_common_end(&cycles));
+    }
+
+    #[test]
+    fn should_report_when_ghosts_never_meet() {
+        let cycles = [
+            GhostCycle {
+                tail: 0,
+                length: 2,
+                tail_ends: vec![],
+                cycle_ends: vec![0],
+            },
+            GhostCycle {
+                tail: 0,
+                length: 4,
+                tail_ends: vec![],
+                cycle_ends: vec![1, 3],
+            },
+        ];
+
+        assert_eq!(Err(GhostError::NoSolution), first_common_end(&cycles));
+        assert_eq!(Err(GhostError::NoGhosts), first_common_end(&[]));
+    }
+
+    #[test]
+    fn should_combine_congruences() {
+        assert_eq!(Ok(Some((18, 35))), crt((3, 5), (4, 7)));
+        assert_eq!(Ok(Some((10, 12))), crt((4, 6), (2, 4)));
+        assert_eq!(Ok(None), crt((1, 4), (2, 6)));
+        assert_eq!(
+            Err(GhostError::Overflow),
+            crt((1, u64::MAX), (2, u64::MAX - 1))
+        );
+    }
+
+    #[test]
+    fn should_solve_large_coprime_cycles() {
+        let (p, q) = (4_294_967_291u64, 4_294_967_279u64);
+        let cycle = |length, end| GhostCycle {
+            tail: 0,
+            length,
+            tail_ends: vec![],
+            cycle_ends: vec![end],
+        };
+
+        assert_eq!(Ok(p * q), first_common_end(&[cycle(p, p), cycle(q, q)]));
+        let end = first_common_end(&[cycle(p, 1), cycle(q, 2)]).unwrap();
+        assert_eq!((1, 2), (end % p, end % q));
+        assert!(end < p * q);
+    }
+
+    #[test]
+    fn should_report_overflow_instead_of_wrapping() {
+        let (p, q, r) = (4_294_967_291u64, 4_294_967_279u64, 4_294_967_231u64);
+        let cycle = |length, end| GhostCycle {
+            tail: 0,
+            length,
+            tail_ends: vec![],
+            cycle_ends: vec![end],
+        };
+
+        assert_eq!(
+            Err(GhostError::Overflow),
+            first_common_end(&[cycle(p, p), cycle(q, q), cycle(r, r)])
+        );
+        assert_eq!(
+            Err(GhostError::Overflow),
+            first_common_end(&[cycle(p, 1), cycle(q, 2), cycle(r, 3)])
+        );
+    }
+
+    #[test]
+    fn should_cap_congruence_blow_up() {
+        let cycles: Vec<GhostCycle> = (0..5)
+            .map(|_| GhostCycle {
+                tail: 0,
+                length: 16,
+                tail_ends: vec![],
+                cycle_ends: (1..16).step_by(2).collect(),
+            })
+            .collect();
+        let many: Vec<GhostCycle> = [17u64, 19, 23, 29, 31, 37]
+            .iter()
+            .map(|length| GhostCycle {
+                tail: 0,
+                length: *length,
+                tail_ends: vec![],
+                cycle_ends: (0..7).collect(),
+            })
+            .collect();
+
+        assert_eq!(Ok(1), first_common_end(&cycles));
+        assert_eq!(
+            Err(GhostError::TooManyCongruences(7 * 7 * 7 * 7 * 7 * 7)),
+            first_common_end(&many)
+        );
+    }
 }